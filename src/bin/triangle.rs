@@ -8,6 +8,7 @@ use pathtracer_rs::scene::obj;
 use pathtracer_rs::vector;
 use std::f64::consts::PI;
 use std::fs;
+use std::path::Path;
 
 fn main() -> image::ImageResult<()> {
     let camera_matrix = matrix::IDENTITY_MATRIX
@@ -19,16 +20,24 @@ fn main() -> image::ImageResult<()> {
         .set_field_of_view(20.0)
         .set_depth_of_field(6.666, 0.07);
 
-    let mut obj_file = fs::File::open("examples/triangle.obj").unwrap();
-    let mut mtl_file = fs::File::open("examples/triangle.mtl").unwrap();
+    let obj_scene = obj::ObjScene::open(
+        Path::new("examples/triangle.obj"),
+        Path::new("examples/triangle.mtl"),
+        &obj::ObjSceneOptions::default(),
+    )
+    .unwrap();
+    for warning in obj_scene.warnings() {
+        eprintln!("{}", warning);
+    }
 
-    let s: Box<dyn scene::Scene> = Box::new(obj::ObjScene::new(&mut obj_file, &mut mtl_file));
+    let s: Box<dyn scene::Scene> = Box::new(obj_scene);
 
     let mut image_writer = Image::new(1024, 1024);
 
     let settings = render::RenderSettings {
-        bounce_depth: 5,
-        samples_per_ray: 1000,
+        bounce_depth: 16,
+        samples_per_ray: 100,
+        ..render::RenderSettings::new()
     };
 
     render::render(&s, &camera, &mut image_writer, &settings);
@@ -4,6 +4,12 @@ pub const BLACK: Color = Color {
     b: 0.0,
 };
 
+pub const WHITE: Color = Color {
+    r: 1.0,
+    g: 1.0,
+    b: 1.0,
+};
+
 #[derive(Clone, Copy)]
 pub struct Color {
     pub r: f64,
@@ -23,4 +29,33 @@ impl Color {
     pub fn multiply(&self, c: Color) -> Color {
         Color::new(self.r * c.r, self.g * c.g, self.b * c.b)
     }
+
+    pub fn scale(&self, multiplier: f64) -> Color {
+        Color::new(
+            self.r * multiplier,
+            self.g * multiplier,
+            self.b * multiplier,
+        )
+    }
+
+    /// Blends linearly from this color at 0 to the other at 1.
+    pub(crate) fn mix(&self, c: Color, t: f64) -> Color {
+        Color::new(
+            mix(self.r, c.r, t),
+            mix(self.g, c.g, t),
+            mix(self.b, c.b, t),
+        )
+    }
+
+    /// The perceived brightness of the color.
+    ///
+    /// https://en.wikipedia.org/wiki/Relative_luminance
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+}
+
+/// Blends linearly from the first value at 0 to the second at 1.
+pub(crate) fn mix(a: f64, b: f64, t: f64) -> f64 {
+    a * (1.0 - t) + b * t
 }
@@ -1,9 +1,75 @@
 use super::ray::Ray;
 use super::vector::Vector;
 
+#[derive(Clone, Copy)]
 pub struct Hit {
     pub from: Ray,
     pub position: Vector,
+    /// The normal of the surface's actual geometry.
     pub normal: Vector,
-    // pub uv: UVCoordinate,
+    /// The normal the surface is shaded with, which may differ from the
+    /// geometric one to make the surface look smoother or bumpier than it
+    /// is.
+    pub shading_normal: Vector,
+    /// How the position changes along the texture's u and v axes, which
+    /// orient normal and bump maps across the surface.
+    pub tangent: Vector,
+    pub bitangent: Vector,
+    pub uv: TextureCoordinate,
+    /// Which of the scene's shapes was hit, so that the scene can look up
+    /// anything else it knows about it.
+    pub shape_index: usize,
+}
+
+impl Hit {
+    /// The normal flipped, if need be, to face the incoming ray, so that
+    /// bounces and lights are measured on the side of the surface that
+    /// was hit.
+    pub fn facing_normal(&self) -> Vector {
+        if self.normal.dot_product(self.from.direction) > 0.0 {
+            return self.normal.scale(-1.0);
+        }
+
+        self.normal
+    }
+
+    /// The shading normal flipped, if need be, to the same side of the
+    /// surface as `facing_normal`.
+    pub fn facing_shading_normal(&self) -> Vector {
+        if self.shading_normal.dot_product(self.facing_normal()) < 0.0 {
+            return self.shading_normal.scale(-1.0);
+        }
+
+        self.shading_normal
+    }
+}
+
+/// A point on a texture, where (0, 0) is its bottom left corner and (1, 1)
+/// is its top right one.
+#[derive(Clone, Copy, Default)]
+pub struct TextureCoordinate {
+    pub u: f64,
+    pub v: f64,
+}
+
+impl TextureCoordinate {
+    pub fn new(u: f64, v: f64) -> TextureCoordinate {
+        TextureCoordinate { u, v }
+    }
+
+    /// Blends the coordinates of a triangle's corners by the barycentric
+    /// weights of a point within it.
+    pub fn interpolate(
+        coordinates: [TextureCoordinate; 3],
+        weights: [f64; 3],
+    ) -> TextureCoordinate {
+        TextureCoordinate::new(
+            coordinates[0].u * weights[0]
+                + coordinates[1].u * weights[1]
+                + coordinates[2].u * weights[2],
+            coordinates[0].v * weights[0]
+                + coordinates[1].v * weights[1]
+                + coordinates[2].v * weights[2],
+        )
+    }
 }
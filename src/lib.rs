@@ -2,16 +2,22 @@ extern crate crossbeam_channel;
 extern crate crossbeam_utils;
 
 pub mod camera;
-mod color;
+pub mod color;
 mod constants;
-mod hit;
+pub mod hit;
 pub mod image_writer;
-mod material;
+pub mod material;
 pub mod matrix;
-mod random;
-mod ray;
+pub mod medium;
+mod onb;
+pub mod random;
+pub mod ray;
 pub mod render;
+mod sampler;
 pub mod scene;
+pub mod shader;
+pub mod spectrum;
+pub mod texture;
 pub mod vector;
 
 #[cfg(test)]
@@ -0,0 +1,81 @@
+use super::super::vector::Vector;
+use super::specular;
+
+/// The share of light reflected off of a smooth boundary between two
+/// dielectrics, averaged across both polarizations. `eta` is the index of
+/// refraction on the far side of the boundary divided by the one on the
+/// near side, and `cosine` is that of the incoming light with the normal.
+///
+/// https://www.pbr-book.org/3ed-2018/Reflection_Models/Specular_Reflection_and_Transmission#FresnelReflectance
+pub fn fresnel(cosine: f64, eta: f64) -> f64 {
+    let cosine_i = cosine.clamp(0.0, 1.0);
+    let sine_t_squared = (1.0 - cosine_i * cosine_i) / (eta * eta);
+
+    // Total internal reflection.
+    if sine_t_squared >= 1.0 {
+        return 1.0;
+    }
+
+    let cosine_t = (1.0 - sine_t_squared).sqrt();
+
+    let parallel = (eta * cosine_i - cosine_t) / (eta * cosine_i + cosine_t);
+    let perpendicular = (cosine_i - eta * cosine_t) / (cosine_i + eta * cosine_t);
+
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Bends the incident vector through a boundary according to Snell's law,
+/// or returns None if it is totally internally reflected instead. The
+/// normal faces the incident side and `eta` is as in `fresnel`.
+///
+/// https://en.wikipedia.org/wiki/Snell%27s_law#Vector_form
+pub fn refract(incident: Vector, normal: Vector, eta: f64) -> Option<Vector> {
+    let cosine_i = -incident.dot_product(normal);
+    let sine_t_squared = (1.0 - cosine_i * cosine_i) / (eta * eta);
+    if sine_t_squared >= 1.0 {
+        return None;
+    }
+
+    let cosine_t = (1.0 - sine_t_squared).sqrt();
+
+    Some(
+        incident
+            .scale(1.0 / eta)
+            .add(normal.scale(cosine_i / eta - cosine_t))
+            .normalize(),
+    )
+}
+
+/// Where a path goes after meeting a smooth dielectric boundary.
+pub enum Scatter {
+    Reflect(Vector),
+    /// The direction, along with the ratio of the indexes of refraction
+    /// it passed between.
+    Transmit(Vector, f64),
+}
+
+/// Reflects or refracts the incident vector in proportion to the Fresnel
+/// reflectance, given `u` in [0, 1). Since the choice is made by the same
+/// odds as the light is split, neither outcome needs weighing.
+///
+/// The normal points out of the material, whichever side the incident
+/// vector comes from, and the index of refraction is that of the material
+/// behind it.
+pub fn scatter(incident: Vector, normal: Vector, index_of_refraction: f64, u: f64) -> Scatter {
+    let entering = incident.dot_product(normal) < 0.0;
+    let (normal, eta) = if entering {
+        (normal, index_of_refraction)
+    } else {
+        (normal.scale(-1.0), 1.0 / index_of_refraction)
+    };
+
+    let cosine = -incident.dot_product(normal);
+    if u < fresnel(cosine, eta) {
+        return Scatter::Reflect(specular::bounce(normal, incident));
+    }
+
+    match refract(incident, normal, eta) {
+        Some(direction) => Scatter::Transmit(direction, eta),
+        None => Scatter::Reflect(specular::bounce(normal, incident)),
+    }
+}
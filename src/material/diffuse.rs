@@ -1,22 +1,63 @@
-use super::super::constants::EPSILON;
+use super::super::color::{Color, BLACK};
+use super::super::onb::Onb;
 use super::super::random::Rng;
 use super::super::vector::Vector;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 
-// Returns a unit vector in the hemisphere of the supplied normal.
+// Returns a unit vector in the hemisphere of the supplied normal, picked
+// in proportion to its cosine with the normal.
+//
+// Points spread evenly across a disk and projected up onto the hemisphere
+// are cosine-distributed, which is known as Malley's method.
+//
+// https://www.pbr-book.org/3ed-2018/Monte_Carlo_Integration/2D_Sampling_with_Multidimensional_Transformations#Cosine-WeightedHemisphereSampling
 pub fn bounce(random: &mut Box<dyn Rng>, normal: Vector) -> Vector {
-    loop {
-        let vector = Vector::new(
-            random.next_f64() * 2.0 - 1.0,
-            random.next_f64() * 2.0 - 1.0,
-            random.next_f64() * 2.0 - 1.0,
-        );
+    let (x, y) = concentric_disk(random.next_2d());
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
 
-        let vector_length = vector.length();
+    Onb::from_normal(normal).world_direction(Vector::new(x, y, z))
+}
+
+// The density with which `bounce` picks the direction.
+pub fn pdf(normal: Vector, direction: Vector) -> f64 {
+    let cosine = normal.dot_product(direction);
+    if cosine <= 0.0 {
+        return 0.0;
+    }
 
-        if vector_length >= 1.0 || vector_length < EPSILON {
-            continue;
-        }
+    cosine / PI
+}
 
-        return vector.scale(vector.dot_product(normal)).normalize();
+// The light a Lambertian surface of the albedo reflects from the
+// direction, including the cosine term. It scatters an albedo's worth of
+// light evenly in every direction, so the BSDF is albedo / PI.
+pub fn reflectance(albedo: Color, normal: Vector, direction: Vector) -> Color {
+    let cosine = normal.dot_product(direction);
+    if cosine <= 0.0 {
+        return BLACK;
     }
+
+    albedo.scale(cosine / PI)
+}
+
+// Maps a point in the unit square onto the unit disk while keeping
+// nearby points nearby, which preserves the stratification of
+// low-discrepancy samples.
+//
+// https://www.pbr-book.org/3ed-2018/Monte_Carlo_Integration/2D_Sampling_with_Multidimensional_Transformations#SamplingaUnitDisk
+fn concentric_disk((u, v): (f64, f64)) -> (f64, f64) {
+    let x = 2.0 * u - 1.0;
+    let y = 2.0 * v - 1.0;
+
+    if x == 0.0 && y == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (radius, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_2 - FRAC_PI_4 * (x / y))
+    };
+
+    (radius * theta.cos(), radius * theta.sin())
 }
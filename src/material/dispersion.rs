@@ -0,0 +1,82 @@
+/// The wavelengths, in nanometers, of the Fraunhofer lines that glass
+/// catalogs measure indexes of refraction at: d, which is yellow, and F
+/// and C, which are blue and red.
+const D_LINE: f64 = 587.56;
+const F_LINE: f64 = 486.13;
+const C_LINE: f64 = 656.27;
+
+/// How a transparent material's index of refraction changes with the
+/// wavelength of light. Shorter wavelengths bend more, which splits white
+/// light into a rainbow through prisms and cut gems.
+///
+/// Both equations take the wavelength in micrometers, as their published
+/// coefficients do.
+///
+/// https://en.wikipedia.org/wiki/Cauchy%27s_equation
+/// https://en.wikipedia.org/wiki/Sellmeier_equation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Fits Cauchy's equation to the index of refraction at the d line and
+    /// the Abbe number, which is how catalogs describe a glass in short.
+    /// Lower Abbe numbers disperse light more, from about 20 for flint
+    /// glass to 65 for crown glass.
+    ///
+    /// https://en.wikipedia.org/wiki/Abbe_number
+    pub fn from_abbe_number(index_of_refraction: f64, abbe_number: f64) -> Dispersion {
+        let inverse_square = |wavelength: f64| (1000.0 / wavelength).powi(2);
+        let b = (index_of_refraction - 1.0)
+            / (abbe_number.max(1.0) * (inverse_square(F_LINE) - inverse_square(C_LINE)));
+
+        Dispersion::Cauchy {
+            a: index_of_refraction - b * inverse_square(D_LINE),
+            b,
+        }
+    }
+
+    /// The index of refraction at the wavelength, in nanometers.
+    pub fn index_of_refraction(&self, wavelength: f64) -> f64 {
+        let squared = (wavelength / 1000.0).powi(2);
+
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / squared,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * squared / (squared - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abbe_number_matches_sellmeier_glass() {
+        // Schott's N-BK7 crown glass.
+        let sellmeier = Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        let index = sellmeier.index_of_refraction(D_LINE);
+        assert!((index - 1.5168).abs() < 1e-4);
+
+        let abbe_number = (index - 1.0)
+            / (sellmeier.index_of_refraction(F_LINE) - sellmeier.index_of_refraction(C_LINE));
+        assert!((abbe_number - 64.17).abs() < 0.1);
+
+        let cauchy = Dispersion::from_abbe_number(index, abbe_number);
+        for &wavelength in [400.0, D_LINE, 700.0].iter() {
+            let difference =
+                cauchy.index_of_refraction(wavelength) - sellmeier.index_of_refraction(wavelength);
+            assert!(difference.abs() < 2e-3);
+        }
+    }
+}
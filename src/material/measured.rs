@@ -0,0 +1,529 @@
+use super::super::color::{Color, BLACK};
+use super::super::hit::Hit;
+use super::super::onb::Onb;
+use super::super::random::Rng;
+use super::super::spectrum;
+use super::super::vector::Vector;
+use super::{Bounce, Material};
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How many half angles, difference angles and difference azimuths MERL
+/// measured each material at.
+const MERL_RESOLUTION: [usize; 3] = [90, 90, 180];
+
+/// MERL files store each channel multiplied by the inverse of these.
+const MERL_SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+/// The resolution of the tables bounces are sampled from: one table for
+/// each range of outgoing angles, each split into cells by the incoming
+/// angle and the azimuth between both directions.
+const OUTGOING_BINS: usize = 16;
+const INCOMING_BINS: usize = 32;
+const AZIMUTH_BINS: usize = 32;
+
+/// The share of each table spread evenly across the hemisphere, so that
+/// even directions the table underestimates can still be sampled.
+const UNIFORM_SHARE: f64 = 0.1;
+
+/// An isotropic BRDF measured from a real material, tabulated by the
+/// angles between the half vector and the normal, between the incoming
+/// direction and the half vector, and around the half vector between
+/// both, as Rusinkiewicz parameterized them. Values are looked up at the
+/// nearest angles measured.
+///
+/// Bounces are importance sampled from tables built when it's loaded,
+/// which hold the reflectance in each direction from a handful of
+/// outgoing angles.
+///
+/// https://www.merl.com/brdf/
+/// https://www.cs.princeton.edu/~smr/papers/brdf_change_of_variables/brdf_change_of_variables.pdf
+pub struct MeasuredBrdf {
+    /// The half angles, difference angles and difference azimuths, in
+    /// radians and in ascending order, that the values were measured at.
+    axes: [Vec<f64>; 3],
+    /// Indexed by the half angle first and the difference azimuth last.
+    values: Vec<Color>,
+    /// The cumulative distribution of each outgoing bin's cells,
+    /// normalized so the last entry is 1.
+    tables: Vec<Vec<f64>>,
+}
+
+impl MeasuredBrdf {
+    /// Tabulates a BRDF from its values at the angles along each axis,
+    /// ordered as in `axes`.
+    pub fn new(axes: [Vec<f64>; 3], values: Vec<Color>) -> MeasuredBrdf {
+        let mut brdf = MeasuredBrdf {
+            axes,
+            values,
+            tables: vec![],
+        };
+        brdf.tables = (0..OUTGOING_BINS).map(|i| brdf.table(i)).collect();
+        brdf
+    }
+
+    /// Loads a MERL .binary file, or a tabulated one if it ends in .csv.
+    pub fn open(path: &Path) -> io::Result<MeasuredBrdf> {
+        let is_csv = match path.extension() {
+            Some(extension) => extension.eq_ignore_ascii_case("csv"),
+            None => false,
+        };
+        if is_csv {
+            return MeasuredBrdf::parse_csv(&fs::read_to_string(path)?);
+        }
+
+        MeasuredBrdf::read_merl(&mut fs::File::open(path)?)
+    }
+
+    /// Reads a BRDF in MERL's binary format: three little endian 32-bit
+    /// integers with the size of each axis, followed by all of the red
+    /// values, then the green and the blue ones as 64-bit floats. Values
+    /// that weren't measured are negative.
+    pub fn read_merl(reader: &mut impl io::Read) -> io::Result<MeasuredBrdf> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        if bytes.len() < 12 {
+            return Err(invalid("missing the dimensions"));
+        }
+
+        let mut dimensions = [0; 3];
+        for (i, dimension) in dimensions.iter_mut().enumerate() {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[i * 4..i * 4 + 4]);
+            *dimension = i32::from_le_bytes(word) as usize;
+        }
+        if dimensions != MERL_RESOLUTION {
+            return Err(invalid("not MERL's resolution"));
+        }
+
+        let count = dimensions.iter().product::<usize>();
+        if bytes.len() != 12 + 3 * count * 8 {
+            return Err(invalid("wrong number of values"));
+        }
+
+        let value = |channel: usize, i: usize| {
+            let start = 12 + (channel * count + i) * 8;
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[start..start + 8]);
+            (f64::from_le_bytes(word) * MERL_SCALE[channel]).max(0.0)
+        };
+        let values = (0..count)
+            .map(|i| Color::new(value(0, i), value(1, i), value(2, i)))
+            .collect();
+
+        // Half angles are spaced more closely near the normal, where
+        // highlights change the fastest.
+        let [half, difference, azimuth] = MERL_RESOLUTION;
+        let axes = [
+            (0..half)
+                .map(|i| ((i as f64 + 0.5) / half as f64).powi(2) * PI / 2.0)
+                .collect(),
+            (0..difference)
+                .map(|i| (i as f64 + 0.5) / difference as f64 * PI / 2.0)
+                .collect(),
+            (0..azimuth)
+                .map(|i| (i as f64 + 0.5) / azimuth as f64 * PI)
+                .collect(),
+        ];
+
+        Ok(MeasuredBrdf::new(axes, values))
+    }
+
+    /// Parses a tabulated BRDF, one measurement per line:
+    ///
+    /// ```text
+    /// theta_half,theta_diff,phi_diff,red,green,blue
+    /// 0,0,0,0.8,0.7,0.6
+    /// ```
+    ///
+    /// with angles in degrees, where every combination of the angles
+    /// found in each column has to be measured. The header line and lines
+    /// starting with `#` are skipped.
+    pub fn parse_csv(input: &str) -> io::Result<MeasuredBrdf> {
+        let invalid = |line: usize, message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", line, message),
+            )
+        };
+
+        let mut rows = vec![];
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("theta_half") {
+                continue;
+            }
+
+            let fields: Result<Vec<f64>, _> = line.split(',').map(|a| a.trim().parse()).collect();
+            match fields {
+                Ok(fields) if fields.len() == 6 && fields.iter().all(|a| a.is_finite()) => {
+                    rows.push((i + 1, fields))
+                }
+                _ => return Err(invalid(i + 1, "expected six finite numbers")),
+            }
+        }
+
+        if rows.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no measurements",
+            ));
+        }
+
+        let mut axes: [Vec<f64>; 3] = [vec![], vec![], vec![]];
+        for (j, axis) in axes.iter_mut().enumerate() {
+            *axis = rows.iter().map(|(_, row)| row[j].to_radians()).collect();
+            axis.sort_by(|a, b| a.total_cmp(b));
+            axis.dedup();
+        }
+
+        let count = axes.iter().map(|axis| axis.len()).product::<usize>();
+        let mut values = vec![BLACK; count];
+        let mut measured = vec![false; count];
+        for (line, row) in rows.iter() {
+            let indexes = [
+                nearest(&axes[0], row[0].to_radians()),
+                nearest(&axes[1], row[1].to_radians()),
+                nearest(&axes[2], row[2].to_radians()),
+            ];
+            let i = index(&axes, indexes);
+            if measured[i] {
+                return Err(invalid(*line, "the angles are measured twice"));
+            }
+            measured[i] = true;
+            values[i] = Color::new(row[3].max(0.0), row[4].max(0.0), row[5].max(0.0));
+        }
+
+        if measured.iter().any(|a| !a) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not every combination of angles is measured",
+            ));
+        }
+
+        Ok(MeasuredBrdf::new(axes, values))
+    }
+
+    /// The BRDF between the directions, which are in the frame of the
+    /// surface with the normal along z.
+    pub fn reflectance(&self, incoming: Vector, outgoing: Vector) -> Color {
+        let half = incoming.add(outgoing).normalize();
+        let theta_half = half.z.clamp(-1.0, 1.0).acos();
+        let phi_half = half.y.atan2(half.x);
+
+        // The incoming direction as seen from the half vector, found by
+        // rotating the half vector onto the normal.
+        let (sin_phi, cos_phi) = phi_half.sin_cos();
+        let (sin_theta, cos_theta) = theta_half.sin_cos();
+        let x = incoming.x * cos_phi + incoming.y * sin_phi;
+        let y = incoming.y * cos_phi - incoming.x * sin_phi;
+        let difference = Vector::new(
+            x * cos_theta - incoming.z * sin_theta,
+            y,
+            x * sin_theta + incoming.z * cos_theta,
+        );
+
+        let theta_difference = difference.z.clamp(-1.0, 1.0).acos();
+        let mut phi_difference = difference.y.atan2(difference.x);
+
+        // Reciprocity makes opposite azimuths the same.
+        if phi_difference < 0.0 {
+            phi_difference += PI;
+        }
+
+        let indexes = [
+            nearest(&self.axes[0], theta_half),
+            nearest(&self.axes[1], theta_difference),
+            nearest(&self.axes[2], phi_difference),
+        ];
+        self.values[index(&self.axes, indexes)]
+    }
+
+    /// Picks an incoming direction for the outgoing one, both in the
+    /// frame of the surface.
+    fn sample_direction(&self, random: &mut Box<dyn Rng>, outgoing: Vector) -> Vector {
+        let table = &self.tables[outgoing_bin(outgoing)];
+
+        let u = random.next_f64();
+        let cell = match table.binary_search_by(|value| value.total_cmp(&u)) {
+            Ok(i) => i,
+            Err(i) => i,
+        }
+        .min(table.len() - 1);
+
+        let ((cosine_0, cosine_1), (azimuth_0, azimuth_1)) = cell_bounds(cell);
+        let (u, v) = random.next_2d();
+        let cosine = cosine_0 + u * (cosine_1 - cosine_0);
+        let mut azimuth = azimuth_0 + v * (azimuth_1 - azimuth_0);
+        if random.next_f64() < 0.5 {
+            azimuth = -azimuth;
+        }
+
+        let phi = outgoing.y.atan2(outgoing.x) + azimuth;
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        Vector::new(sine * phi.cos(), sine * phi.sin(), cosine)
+    }
+
+    /// The density with which `sample_direction` picks the incoming
+    /// direction.
+    fn direction_pdf(&self, incoming: Vector, outgoing: Vector) -> f64 {
+        if incoming.z <= 0.0 {
+            return 0.0;
+        }
+
+        let table = &self.tables[outgoing_bin(outgoing)];
+        let theta = incoming.z.min(1.0).acos();
+        let mut azimuth = (incoming.y.atan2(incoming.x) - outgoing.y.atan2(outgoing.x)).abs();
+        if azimuth > PI {
+            azimuth = 2.0 * PI - azimuth;
+        }
+
+        let i = ((theta / (PI / 2.0)) * INCOMING_BINS as f64) as usize;
+        let j = ((azimuth / PI) * AZIMUTH_BINS as f64) as usize;
+        let cell = i.min(INCOMING_BINS - 1) * AZIMUTH_BINS + j.min(AZIMUTH_BINS - 1);
+
+        let probability = table[cell] - if cell > 0 { table[cell - 1] } else { 0.0 };
+
+        // Each cell covers the azimuths on both sides of the outgoing
+        // direction.
+        probability / (2.0 * cell_solid_angle(cell))
+    }
+
+    /// Builds the sampling table of the outgoing bin, in which each cell
+    /// is weighed by the reflectance towards its center times its solid
+    /// angle.
+    fn table(&self, bin: usize) -> Vec<f64> {
+        let theta = (bin as f64 + 0.5) / OUTGOING_BINS as f64 * PI / 2.0;
+        let outgoing = Vector::new(theta.sin(), 0.0, theta.cos());
+
+        let cell_count = INCOMING_BINS * AZIMUTH_BINS;
+        let mut weights = Vec::with_capacity(cell_count);
+        for cell in 0..cell_count {
+            let ((cosine_0, cosine_1), (azimuth_0, azimuth_1)) = cell_bounds(cell);
+            let cosine = (cosine_0 + cosine_1) / 2.0;
+            let azimuth = (azimuth_0 + azimuth_1) / 2.0;
+            let sine = (1.0 - cosine * cosine).sqrt();
+            let incoming = Vector::new(sine * azimuth.cos(), sine * azimuth.sin(), cosine);
+
+            let reflectance = self.reflectance(incoming, outgoing).luminance().max(0.0);
+            weights.push(reflectance * cosine * cell_solid_angle(cell));
+        }
+
+        let total: f64 = weights.iter().sum();
+        let mut cdf = Vec::with_capacity(cell_count);
+        let mut sum = 0.0;
+        for (cell, weight) in weights.iter().enumerate() {
+            // Half of the hemisphere's solid angle is covered by the cells,
+            // as they only span one side of the outgoing direction.
+            let uniform = cell_solid_angle(cell) / PI;
+            sum += if total > 0.0 {
+                (1.0 - UNIFORM_SHARE) * weight / total + UNIFORM_SHARE * uniform
+            } else {
+                uniform
+            };
+            cdf.push(sum);
+        }
+
+        for value in cdf.iter_mut() {
+            *value /= sum;
+        }
+
+        cdf
+    }
+
+    /// The frame in which the BRDF is evaluated, around the shading
+    /// normal, along with the direction towards where the hit's ray came
+    /// from.
+    fn local_frame(&self, hit: &Hit) -> (Onb, Vector) {
+        let towards_ray = hit.from.direction.scale(-1.0);
+        let mut normal = hit.facing_shading_normal();
+        if normal.dot_product(towards_ray) <= 0.0 {
+            normal = hit.facing_normal();
+        }
+
+        let onb = Onb::from_normal(normal);
+        (onb, onb.local_direction(towards_ray))
+    }
+}
+
+impl Material for MeasuredBrdf {
+    fn emittance(&self, _hit: &Hit) -> Color {
+        BLACK
+    }
+
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color {
+        let (onb, outgoing) = self.local_frame(hit);
+        let incoming = onb.local_direction(direction);
+        if outgoing.z <= 0.0
+            || incoming.z <= 0.0
+            || direction.dot_product(hit.facing_normal()) <= 0.0
+        {
+            return BLACK;
+        }
+
+        let reflectance = self.reflectance(incoming, outgoing).scale(incoming.z);
+        spectrum::upsample(reflectance, hit.from.wavelengths)
+    }
+
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64 {
+        let (onb, outgoing) = self.local_frame(hit);
+        self.direction_pdf(onb.local_direction(direction), outgoing)
+    }
+
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        let (onb, outgoing) = self.local_frame(hit);
+        let direction = onb.world_direction(self.sample_direction(random, outgoing));
+        if direction.dot_product(hit.facing_normal()) <= 0.0 {
+            return None;
+        }
+
+        let pdf = self.pdf(hit, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(Bounce {
+            direction,
+            weight: self.evaluate(hit, direction).scale(1.0 / pdf),
+            pdf: Some(pdf),
+        })
+    }
+}
+
+// The index of the value closest to the angle along the ascending axis.
+fn nearest(axis: &[f64], angle: f64) -> usize {
+    match axis.binary_search_by(|value| value.total_cmp(&angle)) {
+        Ok(i) => i,
+        Err(0) => 0,
+        Err(i) if i == axis.len() => i - 1,
+        Err(i) => {
+            if angle - axis[i - 1] < axis[i] - angle {
+                i - 1
+            } else {
+                i
+            }
+        }
+    }
+}
+
+fn index(axes: &[Vec<f64>; 3], [i, j, k]: [usize; 3]) -> usize {
+    (i * axes[1].len() + j) * axes[2].len() + k
+}
+
+fn outgoing_bin(outgoing: Vector) -> usize {
+    let theta = outgoing.z.clamp(0.0, 1.0).acos();
+    (((theta / (PI / 2.0)) * OUTGOING_BINS as f64) as usize).min(OUTGOING_BINS - 1)
+}
+
+// The cosines of the incoming angles and the azimuths from the outgoing
+// direction that bound the cell. Cells are spaced evenly by angle.
+fn cell_bounds(cell: usize) -> ((f64, f64), (f64, f64)) {
+    let (i, j) = (cell / AZIMUTH_BINS, cell % AZIMUTH_BINS);
+    let theta = |i: usize| i as f64 / INCOMING_BINS as f64 * PI / 2.0;
+    let azimuth = |j: usize| j as f64 / AZIMUTH_BINS as f64 * PI;
+
+    (
+        (theta(i).cos(), theta(i + 1).cos()),
+        (azimuth(j), azimuth(j + 1)),
+    )
+}
+
+fn cell_solid_angle(cell: usize) -> f64 {
+    let ((cosine_0, cosine_1), (azimuth_0, azimuth_1)) = cell_bounds(cell);
+    (cosine_0 - cosine_1) * (azimuth_1 - azimuth_0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hit::TextureCoordinate;
+    use super::super::super::random::pcg::Pcg32;
+    use super::super::super::ray::Ray;
+    use super::*;
+
+    #[test]
+    fn sampling_matches_evaluation() {
+        // A rough, glossy table: half angles every 10 degrees and
+        // difference angles every 30, brightest towards the normal.
+        let mut csv = String::from("theta_half,theta_diff,phi_diff,red,green,blue\n");
+        for half in (0..=90).step_by(10) {
+            for difference in (0..=90).step_by(30) {
+                for azimuth in [0, 90, 180].iter() {
+                    let value = 0.1 + 2.0 * (-(half as f64 / 15.0).powi(2)).exp();
+                    csv += &format!(
+                        "{},{},{},{},{},{}\n",
+                        half,
+                        difference,
+                        azimuth,
+                        value,
+                        value,
+                        value * 0.5
+                    );
+                }
+            }
+        }
+        let brdf = MeasuredBrdf::parse_csv(&csv).unwrap();
+
+        let cosine: f64 = 0.7;
+        let direction = Vector::new((1.0 - cosine * cosine).sqrt(), 0.0, -cosine);
+        let hit = Hit {
+            from: Ray::new(direction.scale(-1.0), direction),
+            position: Vector::zeros(),
+            normal: Vector::new(0.0, 0.0, 1.0),
+            shading_normal: Vector::new(0.0, 0.0, 1.0),
+            tangent: Vector::new(1.0, 0.0, 0.0),
+            bitangent: Vector::new(0.0, 1.0, 0.0),
+            uv: TextureCoordinate::default(),
+            shape_index: 0,
+        };
+
+        // Estimate the albedo both by importance sampling and by sampling
+        // the hemisphere evenly, which only agree if the pdf is right.
+        let mut random: Box<dyn Rng> = Box::new(Pcg32::new(3, 5));
+        let count = 200_000;
+        let (mut sampled, mut uniform) = (0.0, 0.0);
+        for _ in 0..count {
+            if let Some(bounce) = brdf.sample(&mut random, &hit) {
+                sampled += bounce.weight.luminance();
+            }
+
+            let (u, v) = random.next_2d();
+            let sine = (1.0 - u * u).sqrt();
+            let phi = 2.0 * PI * v;
+            let direction = Vector::new(sine * phi.cos(), sine * phi.sin(), u);
+            uniform += brdf.evaluate(&hit, direction).luminance() * 2.0 * PI;
+        }
+
+        let (sampled, uniform) = (sampled / count as f64, uniform / count as f64);
+        assert!(uniform > 0.1);
+        assert!((sampled - uniform).abs() < 0.02 * uniform);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        let header = "theta_half,theta_diff,phi_diff,red,green,blue\n";
+        let tables = [
+            String::new(),
+            header.to_string(),
+            format!("{}nan,0,0,1,1,1\n", header),
+            // The second row measures the first angles again and leaves
+            // (90, 0, 0) out.
+            format!("{}0,0,0,1,1,1\n0,0,0,1,1,1\n", header) + "90,0,0,1,1,1\n",
+        ];
+        for table in tables.iter() {
+            assert!(MeasuredBrdf::parse_csv(table).is_err());
+        }
+
+        // Directions from degenerate normals look up some value rather
+        // than panicking.
+        let brdf = MeasuredBrdf::parse_csv(&format!("{}0,0,0,1,1,1\n", header)).unwrap();
+        let nan = Vector::new(f64::NAN, f64::NAN, f64::NAN);
+        brdf.reflectance(nan, nan);
+        brdf.direction_pdf(nan, nan);
+        let mut random: Box<dyn Rng> = Box::new(Pcg32::new(0, 0));
+        brdf.sample_direction(&mut random, nan);
+    }
+}
@@ -0,0 +1,359 @@
+use super::super::vector::Vector;
+use std::f64::consts::{PI, TAU};
+
+/// Below this roughness a surface is treated as a perfect mirror, since
+/// the distributions become too sharp to evaluate reliably.
+pub const MIN_ALPHA: f64 = 1e-3;
+
+/// How the normals of the tiny facets making up a rough surface are
+/// spread around the surface's own normal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    /// A Gaussian spread of slopes, which is what the Phong exponent of
+    /// older materials approximates.
+    Beckmann,
+    /// Also known as Trowbridge-Reitz. Its longer tails give highlights a
+    /// glow that measured materials tend to share.
+    Ggx,
+}
+
+/// A rough surface made of perfectly reflecting facets.
+///
+/// All directions are in the local frame of the surface, where the normal
+/// is the Z-axis, and point away from the surface.
+///
+/// https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
+#[derive(Clone, Copy)]
+pub struct Microfacet {
+    pub distribution: Distribution,
+    /// The roughness along the local X- and Y-axes, which differ for
+    /// anisotropic surfaces like brushed metal.
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Microfacet {
+    pub fn new(distribution: Distribution, alpha: f64) -> Microfacet {
+        Microfacet {
+            distribution,
+            alpha_x: alpha,
+            alpha_y: alpha,
+        }
+    }
+
+    /// Converts the Phong exponent of an MTL's Ns into the Beckmann
+    /// roughness with the most similar highlight.
+    pub fn from_specular_exponent(specular_exponent: f64) -> Microfacet {
+        let alpha = (2.0 / (specular_exponent.max(0.0) + 2.0)).sqrt();
+        Microfacet::new(Distribution::Beckmann, alpha)
+    }
+
+    /// Converts a perceptual roughness, such as the Pr of the PBR MTL
+    /// extension, into a GGX roughness. Squaring it makes a linear ramp of
+    /// values look like a linear ramp of blurriness.
+    ///
+    /// https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf
+    pub fn from_roughness(roughness: f64) -> Microfacet {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Microfacet::new(Distribution::Ggx, roughness * roughness)
+    }
+
+    /// Whether the surface is smooth enough to be a mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < MIN_ALPHA
+    }
+
+    /// The density of facets facing the normal, with respect to the
+    /// projected area of the surface.
+    pub fn d(&self, normal: Vector) -> f64 {
+        if normal.z <= 0.0 {
+            return 0.0;
+        }
+
+        let x = normal.x / self.alpha_x;
+        let y = normal.y / self.alpha_y;
+        let cosine_squared = normal.z * normal.z;
+
+        match self.distribution {
+            Distribution::Beckmann => {
+                let tangent_squared = (x * x + y * y) / cosine_squared;
+                (-tangent_squared).exp()
+                    / (PI * self.alpha_x * self.alpha_y * cosine_squared * cosine_squared)
+            }
+            Distribution::Ggx => {
+                let denominator = x * x + y * y + cosine_squared;
+                1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+            }
+        }
+    }
+
+    /// The Smith auxiliary function, which measures how much of the
+    /// surface seen from the direction is hidden behind other facets.
+    ///
+    /// https://jcgt.org/published/0003/02/03/paper.pdf
+    fn lambda(&self, direction: Vector) -> f64 {
+        let cosine_squared = direction.z * direction.z;
+        let sine_squared = (1.0 - cosine_squared).max(0.0);
+        if sine_squared <= 0.0 {
+            return 0.0;
+        }
+
+        // The roughness seen from the direction, projected onto the
+        // tangent plane.
+        let x = direction.x * self.alpha_x;
+        let y = direction.y * self.alpha_y;
+        let alpha_tangent_squared = (x * x + y * y) / cosine_squared;
+
+        match self.distribution {
+            Distribution::Beckmann => {
+                let a = 1.0 / alpha_tangent_squared.sqrt();
+                if a >= 1.6 {
+                    return 0.0;
+                }
+                (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+            }
+            Distribution::Ggx => ((1.0 + alpha_tangent_squared).sqrt() - 1.0) / 2.0,
+        }
+    }
+
+    /// The share of facets facing the normal that are visible from the
+    /// direction.
+    pub fn g1(&self, direction: Vector) -> f64 {
+        1.0 / (1.0 + self.lambda(direction))
+    }
+
+    /// The share of facets visible from both directions at once.
+    pub fn g(&self, outgoing: Vector, incoming: Vector) -> f64 {
+        1.0 / (1.0 + self.lambda(outgoing) + self.lambda(incoming))
+    }
+
+    /// Picks a facet normal in proportion to how much of it is visible
+    /// from the outgoing direction, given two numbers in [0, 1).
+    ///
+    /// Rather than picking from every facet and discarding the hidden
+    /// ones, the distribution is stretched into one of unit roughness,
+    /// where the visible facets can be sampled directly, and back again.
+    ///
+    /// https://jcgt.org/published/0007/04/01/paper.pdf
+    /// https://www.pbr-book.org/3ed-2018/Reflection_Models/Microfacet_Models#SamplingtheDistributionofVisibleNormals
+    pub fn sample_normal(&self, outgoing: Vector, (u1, u2): (f64, f64)) -> Vector {
+        let stretched = Vector::new(
+            self.alpha_x * outgoing.x,
+            self.alpha_y * outgoing.y,
+            outgoing.z,
+        )
+        .normalize();
+
+        let (slope_x, slope_y) = match self.distribution {
+            Distribution::Beckmann => beckmann_visible_slope(stretched, u1, u2),
+            Distribution::Ggx => ggx_visible_slope(stretched, u1, u2),
+        };
+
+        Vector::new(-slope_x * self.alpha_x, -slope_y * self.alpha_y, 1.0).normalize()
+    }
+
+    /// The density with which `sample_normal` picks the facet normal.
+    pub fn normal_pdf(&self, outgoing: Vector, normal: Vector) -> f64 {
+        if outgoing.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(outgoing) * outgoing.dot_product(normal).max(0.0) * self.d(normal) / outgoing.z
+    }
+
+    /// How much light arriving from the incoming direction is reflected
+    /// towards the outgoing direction, including the cosine of the
+    /// incoming direction but not the Fresnel reflectance.
+    pub fn reflectance(&self, outgoing: Vector, incoming: Vector) -> f64 {
+        if outgoing.z <= 0.0 || incoming.z <= 0.0 {
+            return 0.0;
+        }
+
+        let half = outgoing.add(incoming).normalize();
+
+        self.d(half) * self.g(outgoing, incoming) / (4.0 * outgoing.z)
+    }
+
+    /// Picks an incoming direction by reflecting the outgoing one off of a
+    /// visible facet, or returns None if it ends up below the surface.
+    pub fn sample_reflection(&self, outgoing: Vector, u: (f64, f64)) -> Option<Vector> {
+        if outgoing.z <= 0.0 {
+            return None;
+        }
+
+        let normal = self.sample_normal(outgoing, u);
+        let incoming = normal
+            .scale(2.0 * outgoing.dot_product(normal))
+            .subtract(outgoing);
+
+        if incoming.z <= 0.0 {
+            return None;
+        }
+
+        Some(incoming)
+    }
+
+    /// The density with which `sample_reflection` picks the incoming
+    /// direction. Reflection doubles the angles between facet normals,
+    /// which spreads the density out by four times the cosine between the
+    /// facet and either direction.
+    pub fn reflection_pdf(&self, outgoing: Vector, incoming: Vector) -> f64 {
+        if outgoing.z <= 0.0 || incoming.z <= 0.0 {
+            return 0.0;
+        }
+
+        let half = outgoing.add(incoming).normalize();
+
+        self.normal_pdf(outgoing, half) / (4.0 * outgoing.dot_product(half))
+    }
+}
+
+/// Picks the slope of a visible facet on a GGX surface of unit roughness
+/// by sampling its projection, which is a disk and half of an ellipse.
+///
+/// https://jcgt.org/published/0007/04/01/paper.pdf (listing 1)
+fn ggx_visible_slope(outgoing: Vector, u1: f64, u2: f64) -> (f64, f64) {
+    let length_squared = outgoing.x * outgoing.x + outgoing.y * outgoing.y;
+    let t1 = if length_squared > 0.0 {
+        Vector::new(-outgoing.y, outgoing.x, 0.0).scale(1.0 / length_squared.sqrt())
+    } else {
+        Vector::new(1.0, 0.0, 0.0)
+    };
+    let t2 = outgoing.cross_product(t1);
+
+    let radius = u1.sqrt();
+    let phi = TAU * u2;
+    let p1 = radius * phi.cos();
+    let s = (1.0 + outgoing.z) / 2.0;
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * radius * phi.sin();
+    let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+    let normal = t1.scale(p1).add(t2.scale(p2)).add(outgoing.scale(p3));
+    let z = normal.z.max(1e-6);
+
+    (-normal.x / z, -normal.y / z)
+}
+
+/// Picks the slope of a visible facet on a Beckmann surface of unit
+/// roughness by inverting the cumulative distribution of its X slope,
+/// then rotating it towards the outgoing direction.
+///
+/// https://www.pbr-book.org/3ed-2018/Reflection_Models/Microfacet_Models#SamplingtheDistributionofVisibleNormals
+/// https://hal.inria.fr/hal-00996995v1/document
+fn beckmann_visible_slope(outgoing: Vector, u1: f64, u2: f64) -> (f64, f64) {
+    let cosine = outgoing.z;
+
+    let (slope_x, slope_y) = if cosine > 0.9999 {
+        let radius = (-(1.0 - u1).ln()).sqrt();
+        let phi = TAU * u2;
+        (radius * phi.cos(), radius * phi.sin())
+    } else {
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let tangent = sine / cosine;
+        let cotangent = 1.0 / tangent;
+        let u1 = u1.max(1e-6);
+
+        // The inverse is found between a and c in the domain of erf, by
+        // Newton's method falling back to bisection.
+        let mut a = -1.0;
+        let mut c = erf(cotangent);
+
+        let theta = cosine.acos();
+        let fit = 1.0 + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+        let mut b = c - (1.0 + c) * (1.0 - u1).powf(fit);
+
+        let sqrt_pi_inverse = 1.0 / PI.sqrt();
+        let normalization =
+            1.0 / (1.0 + c + sqrt_pi_inverse * tangent * (-cotangent * cotangent).exp());
+
+        for _ in 0..10 {
+            if !(b >= a && b <= c) {
+                b = (a + c) / 2.0;
+            }
+
+            let inverse = erf_inverse(b);
+            let value = normalization
+                * (1.0 + b + sqrt_pi_inverse * tangent * (-inverse * inverse).exp())
+                - u1;
+            if value.abs() < 1e-5 {
+                break;
+            }
+
+            if value > 0.0 {
+                c = b;
+            } else {
+                a = b;
+            }
+
+            let derivative = normalization * (1.0 - inverse * tangent);
+            b -= value / derivative;
+        }
+
+        (erf_inverse(b), erf_inverse(2.0 * u2.max(1e-6) - 1.0))
+    };
+
+    // Rotate the slope from the XZ-plane to face the outgoing direction.
+    let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+    let (cos_phi, sin_phi) = if sine > 0.0 {
+        (
+            (outgoing.x / sine).clamp(-1.0, 1.0),
+            (outgoing.y / sine).clamp(-1.0, 1.0),
+        )
+    } else {
+        (1.0, 0.0)
+    };
+
+    (
+        cos_phi * slope_x - sin_phi * slope_y,
+        sin_phi * slope_x + cos_phi * slope_y,
+    )
+}
+
+/// The error function, to within 1.5e-7.
+///
+/// https://en.wikipedia.org/wiki/Error_function#Numerical_approximations
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+
+    sign * (1.0 - polynomial * (-x * x).exp())
+}
+
+/// The inverse of the error function.
+///
+/// https://people.maths.ox.ac.uk/gilesm/codes/erfinv/gems.pdf
+fn erf_inverse(x: f64) -> f64 {
+    let x = x.clamp(-0.99999, 0.99999);
+    let w = -((1.0 - x) * (1.0 + x)).ln();
+
+    let p = if w < 5.0 {
+        let w = w - 2.5;
+        let mut p = 2.81022636e-08;
+        p = 3.43273939e-07 + p * w;
+        p = -3.5233877e-06 + p * w;
+        p = -4.39150654e-06 + p * w;
+        p = 0.00021858087 + p * w;
+        p = -0.00125372503 + p * w;
+        p = -0.00417768164 + p * w;
+        p = 0.246640727 + p * w;
+        1.50140941 + p * w
+    } else {
+        let w = w.sqrt() - 3.0;
+        let mut p = -0.000200214257;
+        p = 0.000100950558 + p * w;
+        p = 0.00134934322 + p * w;
+        p = -0.00367342844 + p * w;
+        p = 0.00573950773 + p * w;
+        p = -0.0076224613 + p * w;
+        p = 0.00943887047 + p * w;
+        p = 1.00167406 + p * w;
+        2.83297682 + p * w
+    };
+
+    p * x
+}
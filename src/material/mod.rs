@@ -1,20 +1,89 @@
+pub mod dielectric;
 pub mod diffuse;
+pub mod dispersion;
+pub mod measured;
+pub mod microfacet;
+pub mod principled;
 pub mod specular;
+pub mod subsurface;
+pub mod thin_film;
+pub mod volume_boundary;
 
 use super::color::Color;
 use super::hit::Hit;
+use super::medium::{Medium, Volume};
 use super::random::Rng;
-use super::ray::Ray;
+use super::vector::Vector;
 
 pub trait Material: Sync {
-    fn sample<'a>(
-        &self,
-        random: &'a mut Box<dyn Rng>,
-        hit: Hit,
-        sampler: Box<dyn MaterialSampler + 'a>,
-    ) -> Color;
+    /// The light emitted by the surface itself.
+    fn emittance(&self, hit: &Hit) -> Color;
+
+    /// How much of the light arriving from the direction is reflected
+    /// back along the hit's ray, including the cosine of the direction
+    /// with the surface. Perfectly specular reflections are excluded
+    /// since they can never be picked by chance.
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color;
+
+    /// The probability density, with respect to solid angle, with which
+    /// `sample` picks a bounce in the direction.
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64;
+
+    /// Picks the direction the path bounces in next, or returns None if
+    /// the surface absorbs it.
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce>;
+
+    /// How much of the surface is there at the hit, from 0 where it is
+    /// cut out to 1 where it is solid. Scenes pass rays through the cut
+    /// out parts before the surface is ever shaded.
+    fn opacity(&self, _hit: &Hit) -> f64 {
+        1.0
+    }
+
+    /// The medium beneath the surface, for translucent materials whose
+    /// bounces into the surface scatter through it before leaving again.
+    /// The integrator walks such bounces through the medium until they
+    /// reach a surface again, where the path carries on.
+    fn subsurface(&self, _hit: &Hit) -> Option<Medium> {
+        None
+    }
+
+    /// The volume filling the closed mesh the surface belongs to, like the
+    /// murk in a jar of water. Paths that bounce through the surface
+    /// travel through the volume until they leave the mesh again.
+    fn interior(&self) -> Option<&Volume> {
+        None
+    }
+
+    /// Whether the surface only marks where a volume begins and ends, like
+    /// the edge of a bank of fog, so that paths and shadow rays pass
+    /// straight through it without being shaded.
+    fn is_interface(&self) -> bool {
+        false
+    }
+
+    /// Whether the surface stays when its closed mesh is filled with a
+    /// volume, since light passes through it like glass around water.
+    /// Otherwise the surface vanishes, leaving smoke or a bank of fog.
+    fn interior_keeps_surface(&self) -> bool {
+        false
+    }
+
+    /// Whether perfectly specular bounces off of the surface bend each of
+    /// a spectral path's wavelengths differently. Such bounces follow the
+    /// hero wavelength, and the integrator drops the others.
+    fn disperses(&self, _hit: &Hit) -> bool {
+        false
+    }
 }
 
-pub trait MaterialSampler: Sync {
-    fn sample<'a>(&self, random: &'a mut Box<dyn Rng>, ray: Ray) -> Color;
+/// The direction a path continues in after hitting a surface.
+pub struct Bounce {
+    pub direction: Vector,
+    /// How much of the light arriving from the direction is reflected
+    /// back along the hit's ray, divided by the pdf.
+    pub weight: Color,
+    /// The density with which the direction was picked, with respect to
+    /// solid angle, or None if it was a perfectly specular bounce.
+    pub pdf: Option<f64>,
 }
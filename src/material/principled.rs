@@ -0,0 +1,586 @@
+use super::super::color::{Color, BLACK, WHITE};
+use super::super::hit::Hit;
+use super::super::onb::Onb;
+use super::super::random::Rng;
+use super::super::spectrum;
+use super::super::vector::Vector;
+use super::dielectric::{self, Scatter};
+use super::dispersion::Dispersion;
+use super::microfacet::{Distribution, Microfacet, MIN_ALPHA};
+use super::specular;
+use super::thin_film::ThinFilm;
+use super::{diffuse, Bounce, Material};
+use std::f64::consts::PI;
+
+/// The reflectance of the clear coat when facing it head on, which is
+/// that of polyurethane with an index of refraction of 1.5.
+const CLEARCOAT_REFLECTANCE: f64 = 0.04;
+
+/// A material meant to cover most real surfaces with a handful of
+/// intuitive parameters, each between 0 and 1 unless noted otherwise.
+///
+/// It layers a rough diffuse base with a sheen for cloth, a specular lobe
+/// that turns into a metal's tinted reflection as the surface becomes
+/// metallic, a clear coat on top and smooth transmission through glassy
+/// surfaces.
+///
+/// https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf
+/// https://blog.selfshadow.com/publications/s2015-shading-course/burley/s2015_pbs_disney_bsdf_notes.pdf
+#[derive(Clone, Copy)]
+pub struct Principled {
+    /// The diffuse albedo, or the color of reflections for metals.
+    pub base_color: Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    /// How much more the surface is stretched along the hit's tangent
+    /// than its bitangent, like brushed metal.
+    pub anisotropic: f64,
+    /// The strength of reflections off of nonmetals, where 0.5 stands
+    /// for an index of refraction of 1.5.
+    pub specular: f64,
+    /// How much reflections off of nonmetals take on the base color.
+    pub specular_tint: f64,
+    /// A soft glow at grazing angles, like that of velvet.
+    pub sheen: f64,
+    /// How much the sheen takes on the base color.
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    /// How much of the nonmetallic surface light passes through rather
+    /// than being diffused, like glass.
+    pub transmission: f64,
+    /// The index of refraction of transmissive surfaces, which is 1 or
+    /// greater.
+    pub index_of_refraction: f64,
+    /// How the index of refraction varies with wavelength in spectral
+    /// renders, which splits light passing through into colors. Other
+    /// renders use `index_of_refraction` for every color.
+    pub dispersion: Option<Dispersion>,
+    pub emission: Color,
+    /// A film over the specular and transmissive parts of the surface,
+    /// which makes their reflections iridescent.
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Principled {
+    /// A white, plastic-like surface that emits no light.
+    pub fn new() -> Principled {
+        Principled {
+            base_color: WHITE,
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropic: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            index_of_refraction: 1.5,
+            dispersion: None,
+            emission: BLACK,
+            thin_film: None,
+        }
+    }
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled::new()
+    }
+}
+
+impl Principled {
+    /// The material as a path carrying the hit's wavelengths sees it, with
+    /// its colors upsampled to spectra.
+    fn at_wavelengths(&self, hit: &Hit) -> Principled {
+        let wavelengths = hit.from.wavelengths;
+        Principled {
+            base_color: spectrum::upsample(self.base_color, wavelengths),
+            emission: spectrum::upsample(self.emission, wavelengths),
+            ..*self
+        }
+    }
+
+    /// The base color with its brightness taken out, which is what tinted
+    /// highlights take on.
+    fn tint(&self) -> Color {
+        let luminance = self.base_color.luminance();
+        if luminance <= 0.0 {
+            return WHITE;
+        }
+
+        self.base_color.scale(1.0 / luminance)
+    }
+
+    /// The reflectance of the specular lobe facing it head on, which
+    /// blends from that of a dielectric to the base color as the surface
+    /// becomes metallic.
+    fn specular_color(&self) -> Color {
+        let dielectric = WHITE
+            .mix(self.tint(), self.specular_tint)
+            .scale(self.specular * 0.08);
+        dielectric.mix(self.base_color, self.metallic)
+    }
+
+    /// The reflectance of the specular lobe at the angle, as seen through
+    /// the thin film if there is one.
+    fn specular_fresnel(&self, hit: &Hit, cosine: f64) -> Color {
+        match (self.thin_film, hit.from.wavelengths) {
+            (Some(film), Some(wavelengths)) => {
+                film.at_wavelengths(wavelengths, cosine, self.specular_color())
+            }
+            (Some(film), None) => film.color(cosine, self.specular_color()),
+            (None, _) => schlick(self.specular_color(), cosine),
+        }
+    }
+
+    /// The index of refraction at the hero wavelength of a spectral path
+    /// through a dispersive surface, and the fixed one otherwise.
+    fn index_of_refraction_at(&self, hit: &Hit) -> f64 {
+        let index = match (self.dispersion, hit.from.wavelengths) {
+            (Some(dispersion), Some(wavelengths)) => {
+                dispersion.index_of_refraction(wavelengths.hero())
+            }
+            _ => self.index_of_refraction,
+        };
+        index.max(1.0)
+    }
+
+    fn specular_microfacet(&self) -> Microfacet {
+        let alpha = self.roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * self.anisotropic.clamp(0.0, 1.0)).sqrt();
+
+        Microfacet {
+            distribution: Distribution::Ggx,
+            alpha_x: (alpha / aspect).max(MIN_ALPHA),
+            alpha_y: (alpha * aspect).max(MIN_ALPHA),
+        }
+    }
+
+    fn clearcoat_microfacet(&self) -> Microfacet {
+        let alpha = self.clearcoat_roughness.clamp(0.0, 1.0).powi(2);
+        Microfacet::new(Distribution::Ggx, alpha.max(MIN_ALPHA))
+    }
+
+    /// How much each lobe weighs in: the diffuse and sheen, the specular,
+    /// the clear coat and the transmission, in that order.
+    fn lobe_weights(&self) -> [f64; 4] {
+        let metallic = self.metallic.clamp(0.0, 1.0);
+        let transmission = self.transmission.clamp(0.0, 1.0) * (1.0 - metallic);
+
+        [
+            (1.0 - metallic) * (1.0 - self.transmission.clamp(0.0, 1.0)),
+            1.0 - transmission,
+            0.25 * self.clearcoat.clamp(0.0, 1.0),
+            transmission,
+        ]
+    }
+
+    /// The chance of sampling each lobe, in proportion to its weight.
+    fn lobe_probabilities(&self) -> [f64; 4] {
+        let weights = self.lobe_weights();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+
+        [
+            weights[0] / total,
+            weights[1] / total,
+            weights[2] / total,
+            weights[3] / total,
+        ]
+    }
+
+    /// The frame in which the material is evaluated, around the shading
+    /// normal and along the tangent, along with the direction towards
+    /// where the hit's ray came from.
+    fn local_frame(&self, hit: &Hit) -> (Onb, Vector) {
+        let towards_ray = hit.from.direction.scale(-1.0);
+        let mut normal = hit.facing_shading_normal();
+        if normal.dot_product(towards_ray) <= 0.0 {
+            normal = hit.facing_normal();
+        }
+
+        let onb = Onb::from_normal_and_tangent(normal, hit.tangent);
+        (onb, onb.local_direction(towards_ray))
+    }
+
+    /// Reflects or refracts the path through the transmissive part of the
+    /// surface, which is tinted by the base color on the way through.
+    /// Spectral paths through dispersive surfaces bend by the index of
+    /// refraction at their hero wavelength.
+    fn sample_transmission(&self, random: &mut Box<dyn Rng>, hit: &Hit, weight: f64) -> Bounce {
+        let incident = hit.from.direction;
+
+        let mut normal = hit.facing_shading_normal();
+        if hit.facing_normal().dot_product(hit.normal) < 0.0 {
+            normal = normal.scale(-1.0);
+        }
+
+        if let Some(film) = self.thin_film {
+            return self.sample_film_transmission(random, hit, normal, film, weight);
+        }
+
+        let index_of_refraction = self.index_of_refraction_at(hit);
+
+        match dielectric::scatter(incident, normal, index_of_refraction, random.next_f64()) {
+            Scatter::Reflect(direction) => Bounce {
+                direction,
+                weight: WHITE.scale(weight),
+                pdf: None,
+            },
+            Scatter::Transmit(direction, eta) => Bounce {
+                direction,
+                weight: self.base_color.scale(weight / (eta * eta)),
+                pdf: None,
+            },
+        }
+    }
+
+    /// Like `sample_transmission` but through a thin film, which reflects
+    /// some colors more than others. The path reflects with the odds of
+    /// the reflectance's luminance and is weighed by how much of each
+    /// color takes the way it went.
+    fn sample_film_transmission(
+        &self,
+        random: &mut Box<dyn Rng>,
+        hit: &Hit,
+        normal: Vector,
+        film: ThinFilm,
+        weight: f64,
+    ) -> Bounce {
+        let incident = hit.from.direction;
+        let index_of_refraction = self.index_of_refraction_at(hit);
+
+        let entering = incident.dot_product(normal) < 0.0;
+        let (normal, outside_index, base_index) = if entering {
+            (normal, 1.0, index_of_refraction)
+        } else {
+            (normal.scale(-1.0), index_of_refraction, 1.0)
+        };
+
+        let cosine = -incident.dot_product(normal);
+        let reflectance =
+            |wavelength: f64| film.reflectance(wavelength, cosine, outside_index, base_index);
+        let reflectance = match hit.from.wavelengths {
+            Some(wavelengths) => wavelengths.map(reflectance),
+            None => spectrum::to_rgb(reflectance),
+        };
+        let probability = reflectance.luminance().clamp(0.0, 1.0);
+
+        let eta = base_index / outside_index;
+        let refracted = dielectric::refract(incident, normal, eta);
+        if random.next_f64() >= probability {
+            if let Some(direction) = refracted {
+                let transmittance = Color::new(
+                    (1.0 - reflectance.r).max(0.0),
+                    (1.0 - reflectance.g).max(0.0),
+                    (1.0 - reflectance.b).max(0.0),
+                );
+                return Bounce {
+                    direction,
+                    weight: transmittance
+                        .multiply(self.base_color)
+                        .scale(weight / ((1.0 - probability) * eta * eta)),
+                    pdf: None,
+                };
+            }
+        }
+
+        let reflection_weight = match refracted {
+            Some(_) => reflectance.scale(weight / probability),
+            None => WHITE.scale(weight),
+        };
+        Bounce {
+            direction: specular::bounce(normal, incident),
+            weight: reflection_weight,
+            pdf: None,
+        }
+    }
+
+    /// Every lobe but the transmission, which is perfectly smooth.
+    ///
+    /// The diffuse lobe brightens at grazing angles on rough surfaces and
+    /// darkens on smooth ones, which is what retroreflection off of rough
+    /// surfaces and the Fresnel reflectance of smooth ones look like.
+    fn evaluate_lobes(&self, hit: &Hit, direction: Vector) -> Color {
+        let (onb, outgoing) = self.local_frame(hit);
+        let incoming = onb.local_direction(direction);
+        if outgoing.z <= 0.0
+            || incoming.z <= 0.0
+            || direction.dot_product(hit.facing_normal()) <= 0.0
+        {
+            return BLACK;
+        }
+
+        let weights = self.lobe_weights();
+        let half = outgoing.add(incoming).normalize();
+        let cosine_d = incoming.dot_product(half);
+
+        let mut color = BLACK;
+
+        if weights[0] > 0.0 {
+            let diffuse_90 = 0.5 + 2.0 * self.roughness * cosine_d * cosine_d;
+            let diffuse = (1.0 + (diffuse_90 - 1.0) * schlick_weight(incoming.z))
+                * (1.0 + (diffuse_90 - 1.0) * schlick_weight(outgoing.z));
+            let sheen = WHITE
+                .mix(self.tint(), self.sheen_tint)
+                .scale(self.sheen * schlick_weight(cosine_d));
+
+            color = self
+                .base_color
+                .scale(diffuse / PI)
+                .add(sheen)
+                .scale(weights[0] * incoming.z);
+        }
+
+        if weights[1] > 0.0 {
+            let reflectance = self.specular_microfacet().reflectance(outgoing, incoming);
+            color = color.add(
+                self.specular_fresnel(hit, cosine_d)
+                    .scale(weights[1] * reflectance),
+            );
+        }
+
+        if weights[2] > 0.0 {
+            let reflectance = self.clearcoat_microfacet().reflectance(outgoing, incoming);
+            let fresnel =
+                CLEARCOAT_REFLECTANCE + (1.0 - CLEARCOAT_REFLECTANCE) * schlick_weight(cosine_d);
+            color = color.add(WHITE.scale(weights[2] * fresnel * reflectance));
+        }
+
+        color
+    }
+
+    /// Picks a lobe in proportion to its weight and bounces according to
+    /// it.
+    fn sample_lobes(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        let probabilities = self.lobe_probabilities();
+        let (onb, outgoing) = self.local_frame(hit);
+
+        let u = random.next_f64();
+        let direction = if u < probabilities[0] {
+            diffuse::bounce(random, onb.w)
+        } else if u < probabilities[0] + probabilities[1] {
+            onb.world_direction(
+                self.specular_microfacet()
+                    .sample_reflection(outgoing, random.next_2d())?,
+            )
+        } else if u < probabilities[0] + probabilities[1] + probabilities[2] {
+            onb.world_direction(
+                self.clearcoat_microfacet()
+                    .sample_reflection(outgoing, random.next_2d())?,
+            )
+        } else if probabilities[3] > 0.0 {
+            let weight = self.lobe_weights()[3] / probabilities[3];
+            return Some(self.sample_transmission(random, hit, weight));
+        } else {
+            return None;
+        };
+
+        if direction.dot_product(hit.facing_normal()) <= 0.0 {
+            return None;
+        }
+
+        // Any of the lobes could have picked the direction, so the bounce
+        // is weighed by all of them.
+        let pdf = self.pdf(hit, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(Bounce {
+            direction,
+            weight: self.evaluate_lobes(hit, direction).scale(1.0 / pdf),
+            pdf: Some(pdf),
+        })
+    }
+}
+
+/// Spectral paths see the material with its colors upsampled at their
+/// wavelengths.
+impl Material for Principled {
+    fn emittance(&self, hit: &Hit) -> Color {
+        self.at_wavelengths(hit).emission
+    }
+
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color {
+        self.at_wavelengths(hit).evaluate_lobes(hit, direction)
+    }
+
+    /// The density of every lobe but the transmission, each weighed by
+    /// the chance of it being picked.
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64 {
+        let (onb, outgoing) = self.local_frame(hit);
+        let incoming = onb.local_direction(direction);
+        let probabilities = self.lobe_probabilities();
+
+        probabilities[0] * diffuse::pdf(onb.w, direction)
+            + probabilities[1]
+                * self
+                    .specular_microfacet()
+                    .reflection_pdf(outgoing, incoming)
+            + probabilities[2]
+                * self
+                    .clearcoat_microfacet()
+                    .reflection_pdf(outgoing, incoming)
+    }
+
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        self.at_wavelengths(hit).sample_lobes(random, hit)
+    }
+
+    fn disperses(&self, _hit: &Hit) -> bool {
+        self.dispersion.is_some() && self.lobe_weights()[3] > 0.0
+    }
+
+    fn interior_keeps_surface(&self) -> bool {
+        self.lobe_weights()[3] > 0.0
+    }
+}
+
+// How much more a surface reflects at the angle than head on, by
+// Schlick's approximation of the Fresnel equations.
+//
+// https://en.wikipedia.org/wiki/Schlick%27s_approximation
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick(reflectance: Color, cosine: f64) -> Color {
+    reflectance.mix(WHITE, schlick_weight(cosine))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::hit::TextureCoordinate;
+    use super::super::super::random::pcg::Pcg32;
+    use super::super::super::ray::Ray;
+    use super::*;
+
+    fn hit(cosine: f64) -> Hit {
+        let direction = Vector::new((1.0 - cosine * cosine).sqrt(), 0.0, -cosine);
+        Hit {
+            from: Ray::new(direction.scale(-1.0), direction),
+            position: Vector::zeros(),
+            normal: Vector::new(0.0, 0.0, 1.0),
+            shading_normal: Vector::new(0.0, 0.0, 1.0),
+            tangent: Vector::new(1.0, 0.0, 0.0),
+            bitangent: Vector::new(0.0, 1.0, 0.0),
+            uv: TextureCoordinate::default(),
+            shape_index: 0,
+        }
+    }
+
+    /// Estimates the light reflected towards the hit's ray from light
+    /// arriving evenly from every direction, by the material's own
+    /// sampling and by sampling the hemisphere uniformly. Both should
+    /// agree if `sample`, `evaluate` and `pdf` are consistent.
+    fn albedos(material: &Principled, hit: &Hit) -> (f64, f64) {
+        let samples = 50_000;
+        let mut random: Box<dyn Rng> = Box::new(Pcg32::new(0, 0));
+
+        let mut sampled = 0.0;
+        let mut uniform = 0.0;
+        for _ in 0..samples {
+            if let Some(bounce) = material.sample(&mut random, hit) {
+                sampled += bounce.weight.g;
+            }
+
+            let (u1, u2) = random.next_2d();
+            let radius = (1.0 - u1 * u1).sqrt();
+            let angle = 2.0 * PI * u2;
+            let direction = Vector::new(radius * angle.cos(), radius * angle.sin(), u1);
+            uniform += material.evaluate(hit, direction).g * 2.0 * PI;
+        }
+
+        (sampled / samples as f64, uniform / samples as f64)
+    }
+
+    #[test]
+    fn sampling_matches_evaluation() {
+        let materials = [
+            Principled::new(),
+            Principled {
+                metallic: 1.0,
+                roughness: 0.3,
+                anisotropic: 0.8,
+                ..Principled::new()
+            },
+            Principled {
+                roughness: 0.8,
+                sheen: 1.0,
+                clearcoat: 1.0,
+                clearcoat_roughness: 0.2,
+                ..Principled::new()
+            },
+            Principled {
+                roughness: 0.2,
+                thin_film: Some(ThinFilm::new(400.0, 1.33)),
+                ..Principled::new()
+            },
+        ];
+
+        for material in materials.iter() {
+            for &cosine in [1.0, 0.4].iter() {
+                let (sampled, uniform) = albedos(material, &hit(cosine));
+                assert!((sampled - uniform).abs() < 0.03 * uniform.max(1.0));
+            }
+        }
+    }
+
+    /// A white metal lit evenly from every direction should reflect no
+    /// more light than reaches it, and glass should let it all through
+    /// one way or another.
+    #[test]
+    fn white_furnace() {
+        for &roughness in [0.1, 0.5, 1.0].iter() {
+            let metal = Principled {
+                metallic: 1.0,
+                roughness,
+                ..Principled::new()
+            };
+            assert!(albedos(&metal, &hit(0.7)).0 <= 1.01);
+        }
+
+        let glass = Principled {
+            transmission: 1.0,
+            index_of_refraction: 1.5,
+            roughness: 0.3,
+            ..Principled::new()
+        };
+        assert!((transmitted_energy(&glass, &hit(0.7)) - 1.0).abs() < 0.01);
+
+        let bubble = Principled {
+            thin_film: Some(ThinFilm::new(400.0, 1.33)),
+            ..glass
+        };
+        assert!((transmitted_energy(&bubble, &hit(0.7)) - 1.0).abs() < 0.02);
+    }
+
+    /// The share of the energy arriving that a transmissive material's
+    /// samples carry on. Radiance entering is concentrated by the square
+    /// of the index of refraction, which is undone to count the energy.
+    fn transmitted_energy(material: &Principled, hit: &Hit) -> f64 {
+        let samples = 50_000;
+        let mut random: Box<dyn Rng> = Box::new(Pcg32::new(0, 0));
+
+        let mut energy = 0.0;
+        for _ in 0..samples {
+            if let Some(bounce) = material.sample(&mut random, hit) {
+                let entered = bounce.direction.dot_product(hit.normal) < 0.0;
+                let scale = if entered {
+                    material.index_of_refraction.powi(2)
+                } else {
+                    1.0
+                };
+                energy += bounce.weight.g * scale;
+            }
+        }
+
+        energy / samples as f64
+    }
+}
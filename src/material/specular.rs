@@ -10,3 +10,4 @@ pub fn bounce(normal: Vector, incident: Vector) -> Vector {
 
     scaled_normal.add(incident)
 }
+
@@ -0,0 +1,68 @@
+use super::super::color::{Color, BLACK, WHITE};
+use super::super::hit::Hit;
+use super::super::medium::Medium;
+use super::super::random::Rng;
+use super::super::vector::Vector;
+use super::{diffuse, Bounce, Material};
+
+/// A translucent material, like skin, wax or marble, that light enters
+/// and scatters through beneath the surface before leaving again, often
+/// some distance away from where it entered.
+///
+/// Light crosses the surface diffusely in both directions. In between,
+/// the integrator walks it through the medium until it finds its way back
+/// out through the mesh, so the mesh should be closed.
+///
+/// The surface has no Fresnel reflection of its own. Light reflected back
+/// inside on the way out would have to carry on walking beneath the
+/// surface, which the integrator doesn't do, so the boundary is left
+/// rough and index matched to keep the two crossings alike.
+///
+/// https://www.pbr-book.org/3ed-2018/Light_Transport_II_Volume_Rendering/Sampling_Volume_Scattering
+/// https://jo.dreggn.org/home/2018_manuka.pdf
+pub struct Subsurface {
+    pub medium: Medium,
+}
+
+impl Subsurface {
+    /// A material that looks the color, where light in each channel
+    /// travels the mean free path on average between scattering events.
+    /// The path is in the scene's units and sets how far light bleeds
+    /// beneath the surface.
+    pub fn new(color: Color, mean_free_path: Color) -> Subsurface {
+        Subsurface {
+            medium: Medium::from_albedo(color, mean_free_path),
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn emittance(&self, _hit: &Hit) -> Color {
+        BLACK
+    }
+
+    /// Light passes through the surface evenly in every direction on the
+    /// far side of it from the hit's ray.
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color {
+        diffuse::reflectance(WHITE, hit.facing_normal().scale(-1.0), direction)
+    }
+
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64 {
+        diffuse::pdf(hit.facing_normal().scale(-1.0), direction)
+    }
+
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        let normal = hit.facing_normal().scale(-1.0);
+        let direction = diffuse::bounce(random, normal);
+
+        Some(Bounce {
+            direction,
+            weight: WHITE,
+            pdf: Some(diffuse::pdf(normal, direction)),
+        })
+    }
+
+    fn subsurface(&self, _hit: &Hit) -> Option<Medium> {
+        Some(self.medium)
+    }
+}
@@ -0,0 +1,159 @@
+use super::super::color::Color;
+use super::super::spectrum::{self, Wavelengths};
+use std::f64::consts::PI;
+
+/// A transparent film about as thin as a wavelength of light, like soap,
+/// oil on water or the coating on a lens. Light reflecting off of the
+/// film's top and bottom interferes, which reflects some wavelengths more
+/// than others depending on the thickness and the angle it's seen from.
+#[derive(Clone, Copy)]
+pub struct ThinFilm {
+    /// In nanometers. Films are colorful from about 100 to 1000.
+    pub thickness: f64,
+    pub index_of_refraction: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, index_of_refraction: f64) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            index_of_refraction,
+        }
+    }
+
+    /// The share of light of the wavelength, in nanometers, reflected off
+    /// of the film where it lies between two dielectrics, averaged across
+    /// both polarizations. `cosine` is that of the incoming light with the
+    /// normal.
+    ///
+    /// Light bounces back and forth inside the film, and the Airy
+    /// summation adds up every bounce along with the difference in phase
+    /// that the trips through the film add.
+    ///
+    /// https://en.wikipedia.org/wiki/Thin-film_interference
+    /// https://belcour.github.io/blog/research/publication/2017/05/01/brdf-thin-film.html
+    pub fn reflectance(
+        &self,
+        wavelength: f64,
+        cosine: f64,
+        outside_index: f64,
+        base_index: f64,
+    ) -> f64 {
+        let cosine_0 = cosine.clamp(0.0, 1.0);
+        let sine_0_squared = 1.0 - cosine_0 * cosine_0;
+        let cosine_of_index = |index: f64| {
+            let sine_squared = sine_0_squared * (outside_index / index).powi(2);
+            if sine_squared >= 1.0 {
+                return None;
+            }
+            Some((1.0 - sine_squared).sqrt())
+        };
+
+        let film_index = self.index_of_refraction;
+        let (cosine_1, cosine_2) = match (cosine_of_index(film_index), cosine_of_index(base_index))
+        {
+            (Some(a), Some(b)) => (a, b),
+            _ => return 1.0,
+        };
+
+        let phase = 4.0 * PI * film_index * self.thickness.max(0.0) * cosine_1 / wavelength;
+        let airy = |top: f64, bottom: f64| {
+            let interference = 2.0 * top * bottom * phase.cos();
+            (top * top + bottom * bottom + interference)
+                / (1.0 + top * top * bottom * bottom + interference)
+        };
+
+        let perpendicular = airy(
+            amplitude(outside_index, cosine_0, film_index, cosine_1),
+            amplitude(film_index, cosine_1, base_index, cosine_2),
+        );
+        let parallel = airy(
+            amplitude(outside_index, cosine_1, film_index, cosine_0),
+            amplitude(film_index, cosine_2, base_index, cosine_1),
+        );
+
+        (perpendicular + parallel) / 2.0
+    }
+
+    /// The color of the film's reflection off of a surface with the given
+    /// reflectance head on, from outside of the surface in the air. The
+    /// surface's reflectance in each channel stands for the index of
+    /// refraction that would reflect it.
+    pub fn color(&self, cosine: f64, base_reflectance: Color) -> Color {
+        let indexes = [
+            base_index(base_reflectance.r),
+            base_index(base_reflectance.g),
+            base_index(base_reflectance.b),
+        ];
+
+        // Each channel's index is used for the wavelengths it covers most,
+        // so that a gray surface reflects the same at every wavelength.
+        let index_at = |wavelength: f64| {
+            if wavelength < 490.0 {
+                indexes[2]
+            } else if wavelength < 580.0 {
+                indexes[1]
+            } else {
+                indexes[0]
+            }
+        };
+
+        spectrum::to_rgb(|wavelength| {
+            self.reflectance(wavelength, cosine, 1.0, index_at(wavelength))
+        })
+    }
+
+    /// Like `color`, but at the wavelengths of a spectral path, where the
+    /// base reflectance is already given at each of them.
+    pub fn at_wavelengths(
+        &self,
+        wavelengths: Wavelengths,
+        cosine: f64,
+        base_reflectance: Color,
+    ) -> Color {
+        let [a, b, c] = wavelengths.0;
+        Color::new(
+            self.reflectance(a, cosine, 1.0, base_index(base_reflectance.r)),
+            self.reflectance(b, cosine, 1.0, base_index(base_reflectance.g)),
+            self.reflectance(c, cosine, 1.0, base_index(base_reflectance.b)),
+        )
+    }
+}
+
+// The index of refraction of a dielectric that reflects the share of
+// light head on from the air.
+fn base_index(reflectance: f64) -> f64 {
+    let root = reflectance.clamp(0.0, 0.99).sqrt();
+    (1.0 + root) / (1.0 - root)
+}
+
+// The amplitude of the wave reflected at the boundary between two
+// indexes of refraction, given the cosines of the angles on either side.
+// Swapping the cosines gives the parallel polarization rather than the
+// perpendicular one.
+fn amplitude(index_i: f64, cosine_i: f64, index_t: f64, cosine_t: f64) -> f64 {
+    (index_i * cosine_i - index_t * cosine_t) / (index_i * cosine_i + index_t * cosine_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dielectric;
+    use super::*;
+
+    #[test]
+    fn vanishing_film_is_a_plain_boundary() {
+        let film = ThinFilm::new(0.0, 1.33);
+        for &cosine in [1.0, 0.6, 0.1].iter() {
+            let reflectance = film.reflectance(550.0, cosine, 1.0, 1.5);
+            assert!((reflectance - dielectric::fresnel(cosine, 1.5)).abs() < 1e-9);
+        }
+
+        // A soap film a few hundred nanometers thick reflects some colors
+        // several times more than others.
+        let color = ThinFilm::new(300.0, 1.33).color(1.0, Color::new(0.0, 0.0, 0.0));
+        let channels = [color.r, color.g, color.b];
+        let most = channels.iter().cloned().fold(0.0, f64::max);
+        let least = channels.iter().cloned().fold(1.0, f64::min);
+        assert!(most > 2.0 * least);
+    }
+}
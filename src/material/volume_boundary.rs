@@ -0,0 +1,74 @@
+use super::super::color::{Color, BLACK, WHITE};
+use super::super::hit::Hit;
+use super::super::medium::Volume;
+use super::super::random::Rng;
+use super::super::vector::Vector;
+use super::{Bounce, Material};
+
+/// Fills a closed mesh with a volume, either behind a surface, like murky
+/// water behind glass, or behind nothing at all, like smoke or fog.
+pub struct VolumeBoundary {
+    /// The surface around the volume, or None if rays pass straight
+    /// through to it.
+    pub surface: Option<Box<dyn Material>>,
+    pub interior: Volume,
+}
+
+impl VolumeBoundary {
+    pub fn new(surface: Option<Box<dyn Material>>, interior: Volume) -> VolumeBoundary {
+        VolumeBoundary { surface, interior }
+    }
+}
+
+impl Material for VolumeBoundary {
+    fn emittance(&self, hit: &Hit) -> Color {
+        self.surface
+            .as_ref()
+            .map_or(BLACK, |surface| surface.emittance(hit))
+    }
+
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color {
+        self.surface
+            .as_ref()
+            .map_or(BLACK, |surface| surface.evaluate(hit, direction))
+    }
+
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64 {
+        self.surface
+            .as_ref()
+            .map_or(0.0, |surface| surface.pdf(hit, direction))
+    }
+
+    /// Without a surface, the path carries on as it was.
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        match &self.surface {
+            Some(surface) => surface.sample(random, hit),
+            None => Some(Bounce {
+                direction: hit.from.direction,
+                weight: WHITE,
+                pdf: None,
+            }),
+        }
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.surface
+            .as_ref()
+            .map_or(1.0, |surface| surface.opacity(hit))
+    }
+
+    fn interior(&self) -> Option<&Volume> {
+        Some(&self.interior)
+    }
+
+    fn is_interface(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    fn disperses(&self, hit: &Hit) -> bool {
+        match &self.surface {
+            Some(surface) => surface.disperses(hit),
+            None => false,
+        }
+    }
+}
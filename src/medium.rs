@@ -0,0 +1,521 @@
+use super::color::{Color, BLACK, WHITE};
+use super::onb::Onb;
+use super::random::Rng;
+use super::ray::Ray;
+use super::spectrum::{self, Wavelengths};
+use super::vector::Vector;
+use std::f64::consts::PI;
+
+/// A volume that light scatters through, described by the fraction of the
+/// light in each channel that is scattered and absorbed for every unit of
+/// distance it travels.
+///
+/// https://www.pbr-book.org/3ed-2018/Volume_Scattering/Volume_Scattering_Processes
+#[derive(Clone, Copy)]
+pub struct Medium {
+    pub scattering: Color,
+    pub absorption: Color,
+    /// Which way light tends to scatter, from -1 straight back to 1 on
+    /// ahead, as the Henyey-Greenstein phase function's g. Zero scatters
+    /// evenly in every direction.
+    pub anisotropy: f64,
+}
+
+impl Medium {
+    pub fn new(scattering: Color, absorption: Color) -> Medium {
+        Medium {
+            scattering,
+            absorption,
+            anisotropy: 0.0,
+        }
+    }
+
+    /// The medium that looks the color after light has scattered through
+    /// it many times, where light in each channel travels the mean free
+    /// path on average before it's either scattered or absorbed.
+    ///
+    /// The albedo of a single scattering event is found from that of many
+    /// with Van de Hulst's fit, as rewritten by Christensen and Burley.
+    ///
+    /// https://graphics.pixar.com/library/ApproxBSSRDF/paper.pdf
+    pub fn from_albedo(color: Color, mean_free_path: Color) -> Medium {
+        let single_scattering_albedo = |albedo: f64| {
+            let albedo = albedo.clamp(0.0, 0.999);
+            let root = (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+            1.0 - (4.09712 + 4.20863 * albedo - root).powi(2)
+        };
+        let extinction = |distance: f64| 1.0 / distance.max(1e-6);
+
+        let scattering = Color::new(
+            single_scattering_albedo(color.r) * extinction(mean_free_path.r),
+            single_scattering_albedo(color.g) * extinction(mean_free_path.g),
+            single_scattering_albedo(color.b) * extinction(mean_free_path.b),
+        );
+        let absorption = Color::new(
+            extinction(mean_free_path.r) - scattering.r,
+            extinction(mean_free_path.g) - scattering.g,
+            extinction(mean_free_path.b) - scattering.b,
+        );
+
+        Medium::new(scattering, absorption)
+    }
+
+    /// The medium as a path carrying the wavelengths sees it, with its
+    /// coefficients upsampled to spectra.
+    pub fn at_wavelengths(&self, wavelengths: Option<Wavelengths>) -> Medium {
+        Medium {
+            scattering: spectrum::upsample(self.scattering, wavelengths),
+            absorption: spectrum::upsample(self.absorption, wavelengths),
+            ..*self
+        }
+    }
+
+    /// The fraction of light either scattered or absorbed per unit of
+    /// distance.
+    pub fn extinction(&self) -> Color {
+        self.scattering.add(self.absorption)
+    }
+
+    /// The fraction of light that travels the distance through the medium
+    /// without being scattered or absorbed.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        // Clear channels let light through forever, even though infinity
+        // times zero is undefined.
+        let transmittance = |extinction: f64| {
+            if extinction <= 0.0 {
+                return 1.0;
+            }
+            (-extinction * distance).exp()
+        };
+
+        let extinction = self.extinction();
+        Color::new(
+            transmittance(extinction.r),
+            transmittance(extinction.g),
+            transmittance(extinction.b),
+        )
+    }
+
+    /// Picks how far light travels through the medium before it scatters,
+    /// or returns None if it makes it the whole way to the surface at
+    /// `max_distance`, which may be infinite. Either way, the path is
+    /// weighed by the returned color.
+    ///
+    /// The distance is picked by the extinction of one channel, chosen in
+    /// proportion to how much light the path still carries in it. Every
+    /// channel is weighed by the combined density of all three, which
+    /// keeps the result unbiased for each of them.
+    ///
+    /// https://www.pbr-book.org/3ed-2018/Light_Transport_II_Volume_Rendering/Sampling_Volume_Scattering#ChromaticMedia
+    pub fn sample_distance(
+        &self,
+        random: &mut Box<dyn Rng>,
+        throughput: Color,
+        max_distance: f64,
+    ) -> (Option<f64>, Color) {
+        let probabilities = match channel_probabilities(throughput) {
+            Some(a) => a,
+            None => return (None, BLACK),
+        };
+        let extinction = channels(self.extinction());
+
+        let u = random.next_f64();
+        let channel = if u < probabilities[0] {
+            0
+        } else if u < probabilities[0] + probabilities[1] {
+            1
+        } else {
+            2
+        };
+        let distance = -(1.0 - random.next_f64()).ln() / extinction[channel];
+
+        if distance >= max_distance {
+            let transmittance = self.transmittance(max_distance);
+            let pdf = dot(probabilities, channels(transmittance));
+            return (None, transmittance.scale(1.0 / pdf));
+        }
+
+        let transmittance = self.transmittance(distance);
+        let pdf = dot(
+            probabilities,
+            channels(self.extinction().multiply(transmittance)),
+        );
+        let weight = self.scattering.multiply(transmittance).scale(1.0 / pdf);
+        (Some(distance), weight)
+    }
+
+    /// The density with which light travelling in the direction scatters
+    /// into the other one, with respect to solid angle.
+    ///
+    /// https://www.pbr-book.org/3ed-2018/Volume_Scattering/Phase_Functions
+    pub fn phase(&self, direction: Vector, scattered: Vector) -> f64 {
+        let g = self.anisotropy.clamp(-0.99, 0.99);
+        let cosine = direction.dot_product(scattered);
+        let denominator = 1.0 + g * g - 2.0 * g * cosine;
+
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Picks the direction light travelling in the direction scatters
+    /// into, in proportion to `phase`.
+    ///
+    /// https://www.pbr-book.org/3ed-2018/Light_Transport_II_Volume_Rendering/Sampling_Volume_Scattering#SamplingPhaseFunctions
+    pub fn sample_phase(&self, direction: Vector, (u, v): (f64, f64)) -> Vector {
+        let g = self.anisotropy.clamp(-0.99, 0.99);
+        let cosine = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - ratio * ratio) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let angle = 2.0 * PI * v;
+
+        Onb::from_normal(direction).world_direction(Vector::new(
+            sine * angle.cos(),
+            sine * angle.sin(),
+            cosine,
+        ))
+    }
+}
+
+/// How dense a medium is at each point of a box, as a grid of samples
+/// that are blended between.
+#[derive(Clone)]
+pub struct DensityGrid {
+    /// The number of samples along x, y and z.
+    pub resolution: [usize; 3],
+    pub min: Vector,
+    pub max: Vector,
+    /// Ordered by x, then y, then z. They're kept private so that the
+    /// largest, which tracking steps by, stays up to date.
+    values: Vec<f64>,
+    max_value: f64,
+}
+
+impl DensityGrid {
+    /// Panics unless there is at least one sample along each axis and a
+    /// value for every sample.
+    pub fn new(resolution: [usize; 3], min: Vector, max: Vector, values: Vec<f64>) -> DensityGrid {
+        assert!(resolution.iter().all(|&count| count >= 1));
+        assert_eq!(values.len(), resolution[0] * resolution[1] * resolution[2]);
+        let max_value = values.iter().cloned().fold(0.0, f64::max);
+
+        DensityGrid {
+            resolution,
+            min,
+            max,
+            values,
+            max_value,
+        }
+    }
+
+    /// Samples the density at every point of the grid.
+    pub fn from_fn(
+        resolution: [usize; 3],
+        min: Vector,
+        max: Vector,
+        density: impl Fn(Vector) -> f64,
+    ) -> DensityGrid {
+        let size = max.subtract(min);
+        let mut values = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let t = |i: usize, count: usize| i as f64 / (count.max(2) - 1) as f64;
+                    let point = min.add(size.multiply(Vector::new(
+                        t(x, resolution[0]),
+                        t(y, resolution[1]),
+                        t(z, resolution[2]),
+                    )));
+                    values.push(density(point).max(0.0));
+                }
+            }
+        }
+
+        DensityGrid::new(resolution, min, max, values)
+    }
+
+    /// The density blended between the nearest samples, or zero outside of
+    /// the box.
+    pub fn density(&self, point: Vector) -> f64 {
+        let size = self.max.subtract(self.min);
+        let local = point.subtract(self.min);
+        let coordinates = [local.x / size.x, local.y / size.y, local.z / size.z];
+        if coordinates.iter().any(|&c| !(0.0..=1.0).contains(&c)) {
+            return 0.0;
+        }
+
+        let mut cells = [0; 3];
+        let mut fractions = [0.0; 3];
+        for axis in 0..3 {
+            let last = self.resolution[axis].saturating_sub(1);
+            let position = coordinates[axis] * last as f64;
+            cells[axis] = (position.floor() as usize).min(last.saturating_sub(1));
+            fractions[axis] = if last == 0 {
+                0.0
+            } else {
+                position - cells[axis] as f64
+            };
+        }
+
+        let value = |x: usize, y: usize, z: usize| {
+            let x = (cells[0] + x).min(self.resolution[0] - 1);
+            let y = (cells[1] + y).min(self.resolution[1] - 1);
+            let z = (cells[2] + z).min(self.resolution[2] - 1);
+            self.values[x + self.resolution[0] * (y + self.resolution[1] * z)]
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+        let [fx, fy, fz] = fractions;
+        lerp(
+            lerp(
+                lerp(value(0, 0, 0), value(1, 0, 0), fx),
+                lerp(value(0, 1, 0), value(1, 1, 0), fx),
+                fy,
+            ),
+            lerp(
+                lerp(value(0, 0, 1), value(1, 0, 1), fx),
+                lerp(value(0, 1, 1), value(1, 1, 1), fx),
+                fy,
+            ),
+            fz,
+        )
+    }
+
+    /// The part of the ray, by distance along it, that lies within the
+    /// box, or None if it misses.
+    fn clip(&self, ray: Ray, max_distance: f64) -> Option<(f64, f64)> {
+        let origin = channels_of(ray.origin);
+        let direction = channels_of(ray.direction);
+        let min = channels_of(self.min);
+        let max = channels_of(self.max);
+
+        let mut near: f64 = 0.0;
+        let mut far = max_distance;
+        for axis in 0..3 {
+            let inverse = 1.0 / direction[axis];
+            let a = (min[axis] - origin[axis]) * inverse;
+            let b = (max[axis] - origin[axis]) * inverse;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+
+        if near >= far {
+            return None;
+        }
+
+        Some((near, far))
+    }
+}
+
+/// A medium filling some part of the scene, either evenly or as dense as
+/// a grid says at each point.
+#[derive(Clone)]
+pub struct Volume {
+    pub medium: Medium,
+    /// Scales the medium's coefficients throughout the grid's box, and
+    /// leaves nothing outside of it. None fills the volume evenly.
+    pub density: Option<DensityGrid>,
+}
+
+impl Volume {
+    pub fn homogeneous(medium: Medium) -> Volume {
+        Volume {
+            medium,
+            density: None,
+        }
+    }
+
+    pub fn heterogeneous(medium: Medium, density: DensityGrid) -> Volume {
+        Volume {
+            medium,
+            density: Some(density),
+        }
+    }
+
+    /// Like `Medium::sample_distance` along the ray, throughout the
+    /// volume. Paths absorbed by heterogeneous volumes are weighed by
+    /// black.
+    ///
+    /// Heterogeneous volumes are sampled by delta tracking: distances are
+    /// picked as though the volume were as dense everywhere as it is at
+    /// its densest, and collisions where it's thinner are turned into
+    /// scattering, absorption or null collisions that carry on unchanged.
+    /// The choice is made by each event's share of the throughput, so
+    /// that colored media converge as well as gray ones.
+    ///
+    /// https://cs.dartmouth.edu/~wjarosz/publications/novak18monte.html
+    /// https://disneyanimation.com/publications/spectral-and-decomposition-tracking-for-rendering-heterogeneous-volumes/
+    pub fn sample_distance(
+        &self,
+        random: &mut Box<dyn Rng>,
+        ray: Ray,
+        throughput: Color,
+        max_distance: f64,
+    ) -> (Option<f64>, Color) {
+        let medium = self.medium.at_wavelengths(ray.wavelengths);
+        let density = match &self.density {
+            Some(a) => a,
+            None => return medium.sample_distance(random, throughput, max_distance),
+        };
+
+        let extinction = medium.extinction();
+        let majorant = density.max_value * channels(extinction).iter().cloned().fold(0.0, f64::max);
+        let (mut distance, far) = match density.clip(ray, max_distance) {
+            Some(a) if majorant > 0.0 => a,
+            _ => return (None, WHITE),
+        };
+
+        let mut weight = WHITE;
+        loop {
+            distance -= (1.0 - random.next_f64()).ln() / majorant;
+            if distance >= far {
+                return (None, weight);
+            }
+
+            let local = density.density(ray.origin.add(ray.direction.scale(distance)));
+            let scattering = medium.scattering.scale(local);
+            let absorption = medium.absorption.scale(local);
+            let null = WHITE.scale(majorant).add(extinction.scale(-local));
+
+            let carried = throughput.multiply(weight);
+            let share = |coefficient: Color| dot(channels(carried), channels(coefficient));
+            let total = share(scattering) + share(absorption) + share(null);
+            if total <= 0.0 {
+                return (None, BLACK);
+            }
+
+            let u = random.next_f64() * total;
+            if u < share(scattering) {
+                let probability = share(scattering) / total;
+                weight = weight
+                    .multiply(scattering)
+                    .scale(1.0 / (majorant * probability));
+                return (Some(distance), weight);
+            }
+            if u < share(scattering) + share(absorption) {
+                return (None, BLACK);
+            }
+
+            let probability = share(null) / total;
+            weight = weight.multiply(null).scale(1.0 / (majorant * probability));
+        }
+    }
+
+    /// The fraction of light that travels the distance along the ray
+    /// without being scattered or absorbed.
+    ///
+    /// Heterogeneous volumes are estimated by ratio tracking, which steps
+    /// through the same collisions as delta tracking but multiplies in the
+    /// chance of each being a null collision rather than picking one.
+    pub fn transmittance(&self, random: &mut Box<dyn Rng>, ray: Ray, distance: f64) -> Color {
+        let medium = self.medium.at_wavelengths(ray.wavelengths);
+        let density = match &self.density {
+            Some(a) => a,
+            None => return medium.transmittance(distance),
+        };
+
+        let extinction = medium.extinction();
+        let majorant = density.max_value * channels(extinction).iter().cloned().fold(0.0, f64::max);
+        let (mut position, far) = match density.clip(ray, distance) {
+            Some(a) if majorant > 0.0 => a,
+            _ => return WHITE,
+        };
+
+        let mut transmittance = WHITE;
+        loop {
+            position -= (1.0 - random.next_f64()).ln() / majorant;
+            if position >= far {
+                return transmittance;
+            }
+
+            let local = density.density(ray.origin.add(ray.direction.scale(position)));
+            transmittance = transmittance.multiply(WHITE.add(extinction.scale(-local / majorant)));
+            if transmittance.luminance() <= 0.0 {
+                return BLACK;
+            }
+        }
+    }
+}
+
+/// The chance of picking each channel in proportion to the throughput, or
+/// None if it carries no light.
+fn channel_probabilities(throughput: Color) -> Option<[f64; 3]> {
+    let weights = channels(throughput);
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    Some([weights[0] / total, weights[1] / total, weights[2] / total])
+}
+
+fn channels(color: Color) -> [f64; 3] {
+    [color.r, color.g, color.b]
+}
+
+fn channels_of(vector: Vector) -> [f64; 3] {
+    [vector.x, vector.y, vector.z]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_albedo_keeps_mean_free_path() {
+        let mean_free_path = Color::new(0.5, 0.1, 2.0);
+        let medium = Medium::from_albedo(Color::new(0.0, 0.5, 0.99), mean_free_path);
+        let extinction = medium.extinction();
+
+        assert!((extinction.r - 2.0).abs() < 1e-9);
+        assert!((extinction.g - 10.0).abs() < 1e-9);
+        assert!((extinction.b - 0.5).abs() < 1e-9);
+
+        // Darker colors come from scattering less often and absorbing
+        // more, but never from absorbing a negative amount.
+        assert!(medium.scattering.r / extinction.r < 1e-3);
+        assert!(medium.scattering.g / extinction.g < medium.scattering.b / extinction.b);
+        assert!(medium.absorption.b >= 0.0);
+    }
+
+    /// A grid that's evenly dense throughout should let through as much
+    /// light as a homogeneous volume, whether it's estimated by ratio
+    /// tracking or by how often delta tracking makes it through.
+    #[test]
+    fn tracking_matches_homogeneous() {
+        let medium = Medium::new(Color::new(0.5, 1.0, 2.0), Color::new(0.5, 0.2, 0.0));
+        let density = DensityGrid::from_fn(
+            [4, 4, 4],
+            Vector::new(-1.0, -1.0, -1.0),
+            Vector::new(1.0, 1.0, 1.0),
+            |_| 1.0,
+        );
+        let volume = Volume::heterogeneous(medium, density);
+        let ray = Ray::new(Vector::new(-2.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let expected = medium.transmittance(1.5);
+
+        let mut random: Box<dyn Rng> = Box::new(super::super::random::pcg::Pcg32::new(0, 0));
+        let samples = 20_000;
+        let mut ratio = BLACK;
+        let mut delta = BLACK;
+        for _ in 0..samples {
+            ratio = ratio.add(volume.transmittance(&mut random, ray, 2.5));
+            if let (None, weight) = volume.sample_distance(&mut random, ray, WHITE, 2.5) {
+                delta = delta.add(weight);
+            }
+        }
+
+        for (estimate, expected) in [ratio, delta].iter().zip([expected, expected].iter()) {
+            let estimate = estimate.scale(1.0 / samples as f64);
+            assert!((estimate.r - expected.r).abs() < 0.02);
+            assert!((estimate.g - expected.g).abs() < 0.02);
+            assert!((estimate.b - expected.b).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use super::constants::EPSILON;
+use super::vector::Vector;
+
+/// An orthonormal basis, which converts directions between world space
+/// and a local frame where `w` points straight up.
+///
+/// https://graphics.pixar.com/library/OrthonormalB/paper.pdf
+#[derive(Clone, Copy)]
+pub struct Onb {
+    pub u: Vector,
+    pub v: Vector,
+    pub w: Vector,
+}
+
+impl Onb {
+    /// Builds a basis around a unit normal without branching on which
+    /// axis it is closest to.
+    pub fn from_normal(normal: Vector) -> Onb {
+        let sign = 1.0_f64.copysign(normal.z);
+        let a = -1.0 / (sign + normal.z);
+        let b = normal.x * normal.y * a;
+
+        Onb {
+            u: Vector::new(
+                1.0 + sign * normal.x * normal.x * a,
+                sign * b,
+                -sign * normal.x,
+            ),
+            v: Vector::new(b, sign + normal.y * normal.y * a, -normal.y),
+            w: normal,
+        }
+    }
+
+    /// Builds a basis around a unit normal whose `u` follows the tangent as
+    /// closely as possible, so that anisotropic materials line up with
+    /// their textures. Tangents along the normal fall back to any basis.
+    pub fn from_normal_and_tangent(normal: Vector, tangent: Vector) -> Onb {
+        let u = tangent.subtract(normal.scale(normal.dot_product(tangent)));
+        if u.length() < EPSILON {
+            return Onb::from_normal(normal);
+        }
+
+        let u = u.normalize();
+
+        Onb {
+            u,
+            v: normal.cross_product(u),
+            w: normal,
+        }
+    }
+
+    /// Converts a direction in the local frame to world space.
+    pub fn world_direction(&self, local: Vector) -> Vector {
+        self.u
+            .scale(local.x)
+            .add(self.v.scale(local.y))
+            .add(self.w.scale(local.z))
+    }
+
+    /// Converts a direction in world space to the local frame.
+    pub fn local_direction(&self, world: Vector) -> Vector {
+        Vector::new(
+            world.dot_product(self.u),
+            world.dot_product(self.v),
+            world.dot_product(self.w),
+        )
+    }
+}
@@ -1,6 +1,42 @@
+pub mod pcg;
 pub mod thread;
 
+/// A source of random numbers.
+///
+/// Generators that draw from low-discrepancy sequences treat each call as
+/// the next dimension of the current sample, so callers that need a point
+/// in two dimensions, such as a position on a lens or a direction across
+/// a hemisphere, should ask for both at once with `next_2d`.
 pub trait Rng {
     /// Within a range of [0, 1.0).
     fn next_f64(&mut self) -> f64;
+
+    /// Two values within a range of [0, 1.0) that are spread evenly
+    /// across the unit square when taken together.
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_f64(), self.next_f64())
+    }
+}
+
+impl Rng for Box<dyn Rng> {
+    fn next_f64(&mut self) -> f64 {
+        (**self).next_f64()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (**self).next_2d()
+    }
+}
+
+/// Mixes a value into a seed, so that seeds for every pixel and sample
+/// can be derived from one seed without their sequences overlapping.
+///
+/// https://prng.di.unimi.it/splitmix64.c
+pub fn hash(seed: u64, value: u64) -> u64 {
+    let mut z = seed
+        .wrapping_add(value.wrapping_mul(0x9e3779b97f4a7c15))
+        .wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
 }
@@ -0,0 +1,50 @@
+use super::Rng;
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+/// A small, fast and seedable generator whose output depends only on its
+/// seed and stream, so the same seed always produces the same render.
+///
+/// https://www.pcg-random.org/download.html
+#[derive(Clone, Copy)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    /// Generators with the same seed but different streams produce
+    /// unrelated sequences.
+    pub fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.state = state.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        let rotation = (state >> 59) as u32;
+
+        xorshifted.rotate_right(rotation)
+    }
+}
+
+impl Rng for Pcg32 {
+    /// Combines two outputs into the 53 bits of precision an f64 holds.
+    fn next_f64(&mut self) -> f64 {
+        let high = (self.next_u32() as u64) << 21;
+        let low = (self.next_u32() >> 11) as u64;
+
+        (high | low) as f64 / (1u64 << 53) as f64
+    }
+}
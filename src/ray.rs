@@ -1,21 +1,39 @@
 use super::matrix::Matrix;
+use super::spectrum::Wavelengths;
 use super::vector::Vector;
 
 #[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Vector,
     pub direction: Vector,
+    /// The wavelengths the path along the ray carries in spectral
+    /// renders, which materials hit by it respond at.
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
     pub fn new(origin: Vector, direction: Vector) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelengths: None,
+        }
+    }
+
+    /// The next ray along the same path, which carries the same
+    /// wavelengths.
+    pub fn spawn(&self, origin: Vector, direction: Vector) -> Ray {
+        Ray {
+            origin,
+            direction,
+            wavelengths: self.wavelengths,
+        }
     }
 
     /// Applies a transformation matrix to each of its origin and
     /// its direction, keeping the direction as a unit vector.
     pub fn transform(&self, m: Matrix) -> Ray {
-        Ray::new(
+        self.spawn(
             self.origin.transform(m),
             self.direction
                 .transform(m.set_translation(Vector::new(0.0, 0.0, 0.0)))
@@ -0,0 +1,151 @@
+use super::super::color::{Color, BLACK};
+use super::tile::Tile;
+use super::ImageWriter;
+
+/// Luminance below which a pixel's error is judged in absolute rather
+/// than relative terms, so that near-black pixels can converge.
+const MIN_RELATIVE_LUMINANCE: f64 = 0.01;
+
+/// A running sum of every sample taken for each pixel of an image.
+///
+/// Alongside the colors, the sum of each sample's squared luminance is
+/// kept so that the variance of a pixel can be estimated.
+///
+/// An accumulation outlives any single call to `render_progressive`, so a
+/// render can be stopped after any pass and later continued with more
+/// samples by passing the same accumulation back in.
+pub struct Accumulation {
+    width: usize,
+    height: usize,
+    colors: Vec<Color>,
+    luminance_squares: Vec<f64>,
+    samples: Vec<usize>,
+    passes: usize,
+}
+
+impl Accumulation {
+    pub fn new(width: usize, height: usize) -> Accumulation {
+        Accumulation {
+            width,
+            height,
+            colors: vec![BLACK; width * height],
+            luminance_squares: vec![0.0; width * height],
+            samples: vec![0; width * height],
+            passes: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// How many passes have been added so far.
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+
+    /// The number of samples taken for a single pixel.
+    pub fn samples(&self, x: usize, y: usize) -> usize {
+        self.samples[self.width * y + x]
+    }
+
+    /// The fewest samples taken for any pixel in the image.
+    pub fn min_samples(&self) -> usize {
+        self.samples.iter().cloned().min().unwrap_or(0)
+    }
+
+    /// The current estimate of a pixel's color, which is the mean of all
+    /// its samples so far.
+    pub fn color(&self, x: usize, y: usize) -> Color {
+        let index = self.width * y + x;
+        let samples = self.samples[index];
+
+        if samples == 0 {
+            return BLACK;
+        }
+
+        self.colors[index].scale(1.0 / samples as f64)
+    }
+
+    /// The standard error of the pixel's mean luminance relative to the
+    /// mean itself. It shrinks as more samples are taken.
+    ///
+    /// https://en.wikipedia.org/wiki/Standard_error
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let index = self.width * y + x;
+        let samples = self.samples[index] as f64;
+
+        if samples < 2.0 {
+            return f64::MAX;
+        }
+
+        let mean = self.colors[index].luminance() / samples;
+        let variance = (self.luminance_squares[index] / samples - mean.powi(2)).max(0.0) * samples
+            / (samples - 1.0);
+        let standard_error = (variance / samples).sqrt();
+
+        standard_error / mean.max(MIN_RELATIVE_LUMINANCE)
+    }
+
+    /// Writes the current estimate of every pixel to the image.
+    pub fn write(&self, image: &mut impl ImageWriter) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.set(x, y, self.color(x, y));
+            }
+        }
+    }
+
+    /// Writes a heat map of how many samples each pixel received, from
+    /// blue for the fewest to red for the most.
+    pub fn write_sample_heat_map(&self, image: &mut impl ImageWriter) {
+        let min = self.min_samples() as f64;
+        let max = self.samples.iter().cloned().max().unwrap_or(0) as f64;
+        let range = (max - min).max(1.0);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = (self.samples(x, y) as f64 - min) / range;
+                image.set(x, y, heat(t));
+            }
+        }
+    }
+
+    /// Adds the sums of a tile's samples, in scanline order within the
+    /// tile, along with how many samples went into each of those sums.
+    pub(super) fn add_tile(
+        &mut self,
+        tile: Tile,
+        colors: &[Color],
+        luminance_squares: &[f64],
+        samples: &[usize],
+    ) {
+        for i in 0..colors.len() {
+            let index = self.width * (tile.y + i / tile.width) + tile.x + i % tile.width;
+            self.colors[index] = self.colors[index].add(colors[i]);
+            self.luminance_squares[index] += luminance_squares[i];
+            self.samples[index] += samples[i];
+        }
+    }
+
+    pub(super) fn finish_pass(&mut self) {
+        self.passes += 1;
+    }
+}
+
+/// Maps [0, 1] onto a blue-green-red color ramp.
+fn heat(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+
+    if t < 0.5 {
+        let s = t * 2.0;
+        return Color::new(0.0, s, 1.0 - s);
+    }
+
+    let s = (t - 0.5) * 2.0;
+    Color::new(s, 1.0 - s, 0.0)
+}
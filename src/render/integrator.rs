@@ -0,0 +1,420 @@
+use super::super::color::{Color, BLACK, WHITE};
+use super::super::constants::EPSILON;
+use super::super::hit::Hit;
+use super::super::material::{Bounce, Material};
+use super::super::medium::{Medium, Volume};
+use super::super::random::Rng;
+use super::super::ray::Ray;
+use super::super::scene::Scene;
+use super::super::spectrum::{self, Wavelengths};
+use super::super::vector::Vector;
+use super::subsurface;
+use super::RenderSettings;
+
+/// Distances to a light are shortened by this fraction before testing
+/// for occlusion so that the light itself doesn't count as a blocker.
+const SHADOW_RAY_TOLERANCE: f64 = 1e-6;
+
+/// Rays that pass through more volume interfaces than this in one go are
+/// given up on, which only happens when they're caught between surfaces
+/// that overlap.
+const MAX_INTERFACE_CROSSINGS: usize = 64;
+
+/// Follows a camera ray as it bounces through the scene and returns the
+/// light it carries back.
+///
+/// The path's throughput is the product of every bounce's weight so far,
+/// which is how much of any light found next will reach the camera.
+///
+/// Between surfaces, the path travels through whichever volume it's in,
+/// which may scatter it along the way. It starts out in the scene's own
+/// volume, as the camera is assumed to sit outside of any closed mesh
+/// that's filled with one.
+///
+/// A camera ray that carries wavelengths makes for a spectral path, whose
+/// light is returned at those wavelengths rather than as a color.
+///
+/// https://www.pbr-book.org/3ed-2018/Light_Transport_I_Surface_Reflection/Path_Tracing
+/// https://www.pbr-book.org/3ed-2018/Light_Transport_II_Volume_Rendering/Volumetric_Light_Transport
+pub fn sample_path(
+    random: &mut Box<dyn Rng>,
+    scene: &Box<dyn Scene>,
+    settings: &RenderSettings,
+    camera_ray: Ray,
+) -> Color {
+    let mut color = BLACK;
+    let mut throughput = WHITE;
+    let mut ray = camera_ray;
+    let mut volume = scene.volume();
+
+    // The density with which the last bounce picked the ray's direction,
+    // or None for camera rays and perfectly specular bounces.
+    let mut pdf: Option<f64> = None;
+
+    // Where a walk beneath a translucent surface came back out, which is
+    // where the path carries on from instead of along a ray.
+    let mut exit: Option<(Hit, &Box<dyn Material>)> = None;
+
+    // Whether a dispersive surface has left only the hero wavelength on
+    // the path.
+    let mut dispersed = false;
+
+    for depth in 0..settings.bounce_depth {
+        let exiting = exit.is_some();
+        let event = match exit.take() {
+            Some((hit, material)) => Event::Surface(Box::new(hit), material),
+            None => trace(random, scene, &mut volume, ray, &mut throughput),
+        };
+
+        let (position, bounce) = match event {
+            Event::Escape => break,
+            Event::Medium(position) => {
+                // Scattering events only happen inside of volumes.
+                let medium = &volume.unwrap().medium;
+                let vertex = Vertex::Medium(position, ray.direction, medium);
+
+                if settings.next_event_estimation {
+                    let light =
+                        sample_light(random, scene, settings, &vertex, volume, ray.wavelengths);
+                    color = color.add(clamp(settings, depth, throughput.multiply(light)));
+                }
+
+                let direction = medium.sample_phase(ray.direction, random.next_2d());
+                let bounce = Bounce {
+                    direction,
+                    weight: WHITE,
+                    pdf: Some(medium.phase(ray.direction, direction)),
+                };
+                (position, bounce)
+            }
+            Event::Surface(hit, material) => {
+                let hit = *hit;
+                let mut emittance = material.emittance(&hit);
+
+                // Lights reached by a bounce could also have been sampled
+                // directly, so their contribution is shared between both
+                // strategies.
+                if settings.next_event_estimation && emittance.luminance() > 0.0 {
+                    if let Some(bounce_pdf) = pdf {
+                        let light_pdf = light_pdf_along_ray(scene, ray.origin, &hit);
+                        emittance =
+                            emittance.scale(settings.mis_heuristic.weight(bounce_pdf, light_pdf));
+                    }
+                }
+
+                if settings.next_event_estimation {
+                    let vertex = Vertex::Surface(&hit, material);
+                    let light =
+                        sample_light(random, scene, settings, &vertex, volume, ray.wavelengths);
+                    emittance = emittance.add(light);
+                }
+
+                color = color.add(clamp(settings, depth, throughput.multiply(emittance)));
+
+                let mut bounce = match material.sample(random, &hit) {
+                    Some(a) => a,
+                    None => break,
+                };
+
+                if let Some(wavelengths) = ray.wavelengths {
+                    if !dispersed && bounce.pdf.is_none() && material.disperses(&hit) {
+                        bounce.weight = wavelengths.keep_hero(bounce.weight);
+                        dispersed = true;
+                    }
+                }
+
+                let crossing = bounce.direction.dot_product(hit.facing_normal()) < 0.0;
+                if crossing && !exiting {
+                    if let Some(medium) = material.subsurface(&hit) {
+                        let walk_ray = ray.spawn(hit.position, bounce.direction);
+                        let (exit_hit, exit_material, weight) =
+                            match subsurface::random_walk(random, scene, medium, walk_ray) {
+                                Some(a) => a,
+                                None => break,
+                            };
+                        throughput = throughput.multiply(weight);
+                        exit = Some((exit_hit, exit_material));
+                        bounce.pdf = None;
+                    }
+                }
+                if crossing {
+                    volume = volume_beyond(scene, volume, &hit, material, bounce.direction);
+                }
+
+                (hit.position, bounce)
+            }
+        };
+
+        throughput = throughput.multiply(bounce.weight);
+        if throughput.luminance() <= 0.0 {
+            break;
+        }
+
+        // Once the path is `roulette_depth` bounces long, it survives each
+        // further bounce with a probability based on how much light it can
+        // still carry. Survivors are brightened by the same factor, which
+        // keeps the result unbiased.
+        //
+        // https://www.pbr-book.org/3ed-2018/Monte_Carlo_Integration/Russian_Roulette_and_Splitting
+        if depth + 1 >= settings.roulette_depth {
+            let survival_probability = throughput.luminance().clamp(
+                settings.min_survival_probability,
+                settings.max_survival_probability,
+            );
+            if random.next_f64() >= survival_probability {
+                break;
+            }
+            throughput = throughput.scale(1.0 / survival_probability);
+        }
+
+        ray = ray.spawn(position, bounce.direction);
+        pdf = bounce.pdf;
+    }
+
+    color
+}
+
+/// What a path runs into next along a ray.
+enum Event<'a> {
+    Surface(Box<Hit>, &'a Box<dyn Material>),
+    /// The path scatters at the point within the volume it's in.
+    Medium(Vector),
+    /// The path leaves the scene or is absorbed.
+    Escape,
+}
+
+/// Follows the ray through the volume it starts in, and on through any
+/// volume interfaces it meets, until it either scatters within a volume
+/// or reaches a surface. The throughput is weighed by how much light
+/// makes it there.
+fn trace<'a>(
+    random: &mut Box<dyn Rng>,
+    scene: &'a Box<dyn Scene>,
+    volume: &mut Option<&'a Volume>,
+    ray: Ray,
+    throughput: &mut Color,
+) -> Event<'a> {
+    let mut ray = ray;
+
+    for _ in 0..MAX_INTERFACE_CROSSINGS {
+        let found = scene.intersect(ray);
+
+        if let Some(current) = *volume {
+            let max_distance = found.as_ref().map_or(f64::INFINITY, |(hit, _)| {
+                hit.position.subtract(ray.origin).length()
+            });
+            let (distance, weight) =
+                current.sample_distance(random, ray, *throughput, max_distance);
+            *throughput = throughput.multiply(weight);
+            if throughput.luminance() <= 0.0 {
+                return Event::Escape;
+            }
+            if let Some(distance) = distance {
+                return Event::Medium(ray.origin.add(ray.direction.scale(distance)));
+            }
+        }
+
+        match found {
+            Some((hit, material)) if material.is_interface() => {
+                *volume = volume_beyond(scene, *volume, &hit, material, ray.direction);
+                ray = ray.spawn(hit.position, ray.direction);
+            }
+            Some((hit, material)) => return Event::Surface(Box::new(hit), material),
+            None => return Event::Escape,
+        }
+    }
+
+    Event::Escape
+}
+
+/// The volume a path is in after passing through the surface in the
+/// direction. Passing into a mesh with an interior enters its volume and
+/// passing out of it returns to the scene's, given that the mesh's
+/// normals point outwards. Other surfaces leave the volume as it was.
+fn volume_beyond<'a>(
+    scene: &'a Box<dyn Scene>,
+    volume: Option<&'a Volume>,
+    hit: &Hit,
+    material: &'a Box<dyn Material>,
+    direction: Vector,
+) -> Option<&'a Volume> {
+    match material.interior() {
+        Some(interior) if direction.dot_product(hit.normal) < 0.0 => Some(interior),
+        Some(_) => scene.volume(),
+        None => volume,
+    }
+}
+
+/// Where a path scatters light found by a shadow ray back along itself.
+enum Vertex<'a> {
+    Surface(&'a Hit, &'a Box<dyn Material>),
+    /// The position and the direction the path was travelling in.
+    Medium(Vector, Vector, &'a Medium),
+}
+
+impl<'a> Vertex<'a> {
+    fn position(&self) -> Vector {
+        match self {
+            Vertex::Surface(hit, _) => hit.position,
+            Vertex::Medium(position, _, _) => *position,
+        }
+    }
+
+    fn evaluate(&self, direction: Vector) -> Color {
+        match self {
+            Vertex::Surface(hit, material) => material.evaluate(hit, direction),
+            Vertex::Medium(_, incoming, medium) => WHITE.scale(medium.phase(*incoming, direction)),
+        }
+    }
+
+    fn pdf(&self, direction: Vector) -> f64 {
+        match self {
+            Vertex::Surface(hit, material) => material.pdf(hit, direction),
+            Vertex::Medium(_, incoming, medium) => medium.phase(*incoming, direction),
+        }
+    }
+}
+
+/// Dims light gathered after the first bounce down to the maximum
+/// luminance in the settings.
+fn clamp(settings: &RenderSettings, depth: usize, color: Color) -> Color {
+    if depth == 0 || settings.max_indirect_luminance <= 0.0 {
+        return color;
+    }
+
+    let luminance = color.luminance();
+    if luminance <= settings.max_indirect_luminance {
+        return color;
+    }
+
+    color.scale(settings.max_indirect_luminance / luminance)
+}
+
+/// Casts a shadow ray towards a point picked on one of the scene's lights
+/// and returns the light scattered from it back along the path, given the
+/// volume the path is in and the wavelengths it carries, if any.
+///
+/// https://www.pbr-book.org/3ed-2018/Light_Transport_I_Surface_Reflection/Direct_Lighting
+fn sample_light(
+    random: &mut Box<dyn Rng>,
+    scene: &Box<dyn Scene>,
+    settings: &RenderSettings,
+    vertex: &Vertex,
+    volume: Option<&Volume>,
+    wavelengths: Option<Wavelengths>,
+) -> Color {
+    let light = match scene.sample_light(random) {
+        Some(a) => a,
+        None => return BLACK,
+    };
+
+    let position = vertex.position();
+    let to_light = light.position.subtract(position);
+    let distance = to_light.length();
+    if distance < EPSILON {
+        return BLACK;
+    }
+    let direction = to_light.scale(1.0 / distance);
+
+    let light_cosine = light.normal.dot_product(direction).abs();
+    if light_cosine < EPSILON {
+        return BLACK;
+    }
+
+    let reflectance = vertex.evaluate(direction);
+    if reflectance.luminance() <= 0.0 {
+        return BLACK;
+    }
+
+    // Shadow rays that pass through the surface start out in the volume
+    // on its far side.
+    let volume = match vertex {
+        Vertex::Surface(hit, material) if direction.dot_product(hit.facing_normal()) < 0.0 => {
+            volume_beyond(scene, volume, hit, material, direction)
+        }
+        _ => volume,
+    };
+
+    let shadow_ray = Ray {
+        wavelengths,
+        ..Ray::new(position, direction)
+    };
+    let transmittance = transmittance(
+        random,
+        scene,
+        volume,
+        shadow_ray,
+        distance * (1.0 - SHADOW_RAY_TOLERANCE),
+    );
+    if transmittance.luminance() <= 0.0 {
+        return BLACK;
+    }
+
+    let light_pdf = light.pdf * distance.powi(2) / light_cosine;
+    let bounce_pdf = vertex.pdf(direction);
+    let weight = settings.mis_heuristic.weight(light_pdf, bounce_pdf);
+
+    spectrum::upsample(light.emittance, wavelengths)
+        .multiply(reflectance)
+        .multiply(transmittance)
+        .scale(weight / light_pdf)
+}
+
+/// The fraction of light that makes it the distance along the ray,
+/// through volumes and the interfaces between them, or black if a surface
+/// is in the way.
+fn transmittance(
+    random: &mut Box<dyn Rng>,
+    scene: &Box<dyn Scene>,
+    volume: Option<&Volume>,
+    ray: Ray,
+    distance: f64,
+) -> Color {
+    let mut volume = volume;
+    let mut ray = ray;
+    let mut remaining = distance;
+    let mut transmittance = WHITE;
+
+    for _ in 0..MAX_INTERFACE_CROSSINGS {
+        let found = scene.intersect(ray);
+        let hit_distance = found.as_ref().map_or(f64::INFINITY, |(hit, _)| {
+            hit.position.subtract(ray.origin).length()
+        });
+
+        if let Some(current) = volume {
+            let step = hit_distance.min(remaining);
+            transmittance = transmittance.multiply(current.transmittance(random, ray, step));
+        }
+
+        match found {
+            Some((hit, material)) if hit_distance < remaining => {
+                if !material.is_interface() {
+                    return BLACK;
+                }
+                volume = volume_beyond(scene, volume, &hit, material, ray.direction);
+                ray = ray.spawn(hit.position, ray.direction);
+                remaining -= hit_distance;
+            }
+            _ => return transmittance,
+        }
+    }
+
+    BLACK
+}
+
+/// The density, with respect to solid angle, with which `sample_light`
+/// would have picked the point hit from the origin.
+fn light_pdf_along_ray(scene: &Box<dyn Scene>, origin: Vector, hit: &Hit) -> f64 {
+    let to_hit = hit.position.subtract(origin);
+    let distance = to_hit.length();
+    if distance < EPSILON {
+        return 0.0;
+    }
+
+    let light_cosine = hit.normal.dot_product(to_hit.scale(1.0 / distance)).abs();
+    if light_cosine < EPSILON {
+        return 0.0;
+    }
+
+    scene.light_pdf(hit) * distance.powi(2) / light_cosine
+}
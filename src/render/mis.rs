@@ -0,0 +1,28 @@
+/// Weighs the samples of one strategy against another that could have
+/// produced the same path, so that combined they remain unbiased.
+///
+/// https://www.pbr-book.org/3ed-2018/Monte_Carlo_Integration/Importance_Sampling#MultipleImportanceSampling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MisHeuristic {
+    Balance,
+    /// The balance heuristic with each pdf squared, which favors whichever
+    /// strategy is most confident.
+    Power,
+}
+
+impl MisHeuristic {
+    /// The weight of a sample taken with the first pdf, given that the
+    /// other strategy would have taken it with the second.
+    pub fn weight(&self, pdf: f64, other_pdf: f64) -> f64 {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (pdf, other_pdf),
+            MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+
+        if a + b <= 0.0 {
+            return 0.0;
+        }
+
+        a / (a + b)
+    }
+}
@@ -0,0 +1,585 @@
+use super::camera::Camera;
+use super::color::{Color, BLACK};
+use super::random as local_random;
+use super::random::Rng;
+use super::sampler::PixelSample;
+use super::scene::Scene;
+use super::spectrum::Wavelengths;
+use crossbeam_channel;
+use crossbeam_utils;
+use num_cpus;
+use std;
+
+mod accumulation;
+mod integrator;
+mod mis;
+mod subsurface;
+mod tile;
+
+pub use super::sampler::SamplerKind;
+pub use accumulation::Accumulation;
+pub use mis::MisHeuristic;
+pub use tile::{Tile, TileOrder};
+
+pub struct RenderSettings {
+    /// The most bounces a path can take.
+    pub bounce_depth: usize,
+    /// Paths that are at least this many bounces long may be ended early
+    /// by Russian roulette.
+    pub roulette_depth: usize,
+    /// The bounds of the probability with which a path survives Russian
+    /// roulette. A maximum below one ensures that even bright paths end.
+    pub min_survival_probability: f64,
+    pub max_survival_probability: f64,
+    /// Light gathered after the first bounce is dimmed to at most this
+    /// luminance, trading a little bias for fewer fireflies. Zero
+    /// disables clamping.
+    pub max_indirect_luminance: f64,
+    /// Whether surfaces cast shadow rays towards the scene's lights
+    /// rather than relying on bounces alone to find them.
+    pub next_event_estimation: bool,
+    /// How light found by shadow rays and by bounces is weighed when both
+    /// could have found it.
+    pub mis_heuristic: MisHeuristic,
+    /// The total number of samples to take for each pixel. With adaptive
+    /// sampling this is the most a pixel will ever receive.
+    pub samples_per_ray: usize,
+    /// The fewest samples a pixel receives before adaptive sampling may
+    /// consider it converged.
+    pub min_samples_per_ray: usize,
+    /// Pixels stop receiving samples once their `relative_error` falls
+    /// below this. Zero disables adaptive sampling.
+    pub adaptive_threshold: f64,
+    /// How many samples each pixel receives before the pass callback of
+    /// `render_progressive` is invoked.
+    pub samples_per_pass: usize,
+    /// Width and height of the square tiles handed out to each thread.
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    /// The sequence from which each sample draws its random numbers.
+    pub sampler: SamplerKind,
+    /// Whether paths carry a few wavelengths of light each rather than
+    /// red, green and blue, which lets surfaces such as dispersive glass
+    /// respond to every wavelength differently. Each sample is converted
+    /// back to a color before it's accumulated.
+    pub spectral: bool,
+    /// Renders with the same seed are identical regardless of how many
+    /// threads render them. None picks a new seed for every render.
+    pub seed: Option<u64>,
+    /// Zero uses one thread per CPU.
+    pub thread_count: usize,
+}
+
+impl RenderSettings {
+    pub fn new() -> RenderSettings {
+        RenderSettings {
+            bounce_depth: 16,
+            roulette_depth: 3,
+            min_survival_probability: 0.05,
+            max_survival_probability: 0.95,
+            max_indirect_luminance: 0.0,
+            next_event_estimation: true,
+            mis_heuristic: MisHeuristic::Power,
+            samples_per_ray: 100,
+            min_samples_per_ray: 16,
+            adaptive_threshold: 0.0,
+            samples_per_pass: 8,
+            tile_size: 32,
+            tile_order: TileOrder::Hilbert,
+            sampler: SamplerKind::Sobol,
+            spectral: false,
+            seed: None,
+            thread_count: 0,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings::new()
+    }
+}
+
+pub trait ImageWriter {
+    fn height(&self) -> usize;
+    fn set(&mut self, x: usize, y: usize, color: Color);
+    fn width(&self) -> usize;
+}
+
+/// Whether `render_progressive` should keep rendering after a pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Progress {
+    Continue,
+    Stop,
+}
+
+/// Renders every sample for every pixel and writes the result to the
+/// image.
+pub fn render<'a>(
+    scene: &'a Box<dyn Scene>,
+    camera: &'a impl Camera,
+    image: &mut impl ImageWriter,
+    settings: &'a RenderSettings,
+) {
+    let mut accumulation = Accumulation::new(image.width(), image.height());
+
+    render_progressive(scene, camera, &mut accumulation, settings, |_| {
+        Progress::Continue
+    });
+
+    accumulation.write(image);
+}
+
+/// Renders the image in passes of `samples_per_pass` samples per pixel,
+/// adding each pass to the accumulation and invoking `on_pass` with it
+/// afterwards.
+///
+/// Rendering stops once every pixel has `samples_per_ray` samples or has
+/// converged below `adaptive_threshold`, or when `on_pass` returns
+/// `Progress::Stop`. An accumulation that already holds
+/// samples from an earlier call picks up where it left off, so a render
+/// can be refined later by raising `samples_per_ray` and calling this
+/// again. The stratified sampler's strata depend on `samples_per_ray`
+/// though, so the refined samples aren't stratified with the earlier ones.
+pub fn render_progressive<'a>(
+    scene: &'a Box<dyn Scene>,
+    camera: &'a impl Camera,
+    accumulation: &mut Accumulation,
+    settings: &'a RenderSettings,
+    mut on_pass: impl FnMut(&Accumulation) -> Progress,
+) {
+    let viewport = Viewport::new(accumulation.width(), accumulation.height());
+
+    let tiles = tile::tiles(
+        viewport.width,
+        viewport.height,
+        settings.tile_size,
+        settings.tile_order,
+    );
+
+    let seed = match settings.seed {
+        Some(seed) => seed,
+        None => {
+            let mut thread_random = local_random::thread::ThreadRng::new();
+            (local_random::Rng::next_f64(&mut thread_random) * u64::MAX as f64) as u64
+        }
+    };
+
+    let thread_count = if settings.thread_count > 0 {
+        settings.thread_count
+    } else {
+        num_cpus::get()
+    };
+    let (tx_tile, rx_tile) = crossbeam_channel::bounded(thread_count);
+    let (tx_colors, rx_colors) = std::sync::mpsc::channel::<TileColorsMessage>();
+    let scene_arc = std::sync::Arc::from(scene);
+    let camera_arc = std::sync::Arc::from(camera);
+    let viewport = &viewport;
+
+    crossbeam_utils::thread::scope(|scope| {
+        for _ in 0..thread_count {
+            let scene = std::sync::Arc::clone(&scene_arc);
+            let camera = std::sync::Arc::clone(&camera_arc);
+            let tx = std::sync::mpsc::Sender::clone(&tx_colors);
+            let rx = rx_tile.clone();
+
+            scope.spawn(move |_| {
+                let renderer = TileRenderer {
+                    scene: &scene,
+                    camera: *camera,
+                    viewport,
+                    settings,
+                    seed,
+                };
+
+                for tile_message in rx {
+                    match tile_message {
+                        TileMessage::Done => break,
+                        TileMessage::Tile(tile, samples) => {
+                            let message = renderer.render(tile, samples);
+                            tx.send(message).unwrap();
+                        }
+                    }
+                }
+            });
+        }
+
+        // Passes are dispatched from this thread, and since the colors
+        // channel is unbounded the workers never block on sending back.
+        loop {
+            let mut pending_tiles = 0;
+
+            for &tile in tiles.iter() {
+                let samples = samples_for_pass(accumulation, settings, tile);
+                if samples.iter().all(|s| s.count == 0) {
+                    continue;
+                }
+
+                tx_tile.send(TileMessage::Tile(tile, samples)).unwrap();
+                pending_tiles += 1;
+            }
+
+            if pending_tiles == 0 {
+                break;
+            }
+
+            for _ in 0..pending_tiles {
+                let message = rx_colors.recv().unwrap();
+                accumulation.add_tile(
+                    message.tile,
+                    &message.colors,
+                    &message.luminance_squares,
+                    &message.samples,
+                );
+            }
+            accumulation.finish_pass();
+
+            if on_pass(accumulation) == Progress::Stop {
+                break;
+            }
+        }
+
+        for _ in 0..thread_count {
+            tx_tile.send(TileMessage::Done).unwrap();
+        }
+    })
+    .unwrap();
+}
+
+/// Decides how many samples each pixel of the tile should receive in the
+/// next pass, in scanline order within the tile.
+fn samples_for_pass(
+    accumulation: &Accumulation,
+    settings: &RenderSettings,
+    tile: Tile,
+) -> Vec<PixelSamples> {
+    let samples_per_pass = settings.samples_per_pass.max(1);
+    let mut samples = Vec::with_capacity(tile.width * tile.height);
+
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            let taken = accumulation.samples(x, y);
+
+            let converged = settings.adaptive_threshold > 0.0
+                && taken >= settings.min_samples_per_ray
+                && accumulation.relative_error(x, y) < settings.adaptive_threshold;
+
+            let count = if converged {
+                0
+            } else {
+                settings
+                    .samples_per_ray
+                    .saturating_sub(taken)
+                    .min(samples_per_pass)
+            };
+
+            samples.push(PixelSamples {
+                first: taken,
+                count,
+            });
+        }
+    }
+
+    samples
+}
+
+/// Maps pixel coordinates onto the 2D plane that cameras cast from.
+struct Viewport {
+    width: usize,
+    height: usize,
+    x_ratio: f64,
+    y_ratio: f64,
+    x_step: f64,
+    y_step: f64,
+}
+
+impl Viewport {
+    fn new(width: usize, height: usize) -> Viewport {
+        let aspect_ratio = width as f64 / height as f64;
+
+        let mut x_ratio: f64 = 1.0;
+        let mut y_ratio: f64 = 1.0;
+        if aspect_ratio < 1.0 {
+            x_ratio = aspect_ratio;
+        } else {
+            y_ratio = 1.0 / aspect_ratio;
+        }
+
+        Viewport {
+            width,
+            height,
+            x_ratio,
+            y_ratio,
+            x_step: x_ratio / (width - 1) as f64,
+            y_step: y_ratio / (height - 1) as f64,
+        }
+    }
+
+    /// Positive x is right and positive y is up.
+    fn position(&self, x_pixel: usize, y_pixel: usize) -> (f64, f64) {
+        let x = self.x_ratio * (x_pixel as f64 / (self.width - 1) as f64 - 0.5);
+        let y = -self.y_ratio * (y_pixel as f64 / (self.height - 1) as f64 - 0.5);
+        (x, y)
+    }
+}
+
+/// Everything a render thread needs to render a tile.
+struct TileRenderer<'a, C: Camera> {
+    scene: &'a Box<dyn Scene>,
+    camera: &'a C,
+    viewport: &'a Viewport,
+    settings: &'a RenderSettings,
+    seed: u64,
+}
+
+impl<'a, C: Camera> TileRenderer<'a, C> {
+    /// Takes the given number of samples for each pixel in the tile and
+    /// returns their sums in scanline order.
+    ///
+    /// Every sample draws from its own generator, picked by the pixel and
+    /// the sample's index within it. The result is therefore the same no
+    /// matter which thread renders the tile or how passes are split up.
+    fn render(&self, tile: Tile, samples: Vec<PixelSamples>) -> TileColorsMessage {
+        let mut colors = Vec::with_capacity(samples.len());
+        let mut luminance_squares = Vec::with_capacity(samples.len());
+        let mut i = 0;
+
+        for y_pixel in tile.y..tile.y + tile.height {
+            for x_pixel in tile.x..tile.x + tile.width {
+                let (x, y) = self.viewport.position(x_pixel, y_pixel);
+
+                let mut color = BLACK;
+                let mut luminance_square = 0.0;
+                let first = samples[i].first;
+                for sample_index in first..first + samples[i].count {
+                    let mut random = self.settings.sampler.sampler(&PixelSample {
+                        x: x_pixel,
+                        y: y_pixel,
+                        index: sample_index,
+                        samples_per_pixel: self.settings.samples_per_ray,
+                        seed: self.seed,
+                    });
+
+                    let (x_rand, y_rand) = random.next_2d();
+                    let mut ray = self.camera.cast(
+                        &mut random,
+                        x + x_rand * self.viewport.x_step,
+                        y + y_rand * self.viewport.y_step,
+                    );
+                    if self.settings.spectral {
+                        ray.wavelengths = Some(Wavelengths::sample(random.next_f64()));
+                    }
+
+                    let mut sample =
+                        integrator::sample_path(&mut random, self.scene, self.settings, ray);
+                    if let Some(wavelengths) = ray.wavelengths {
+                        sample = wavelengths.to_rgb(sample);
+                    }
+                    color = color.add(sample);
+                    luminance_square += sample.luminance().powi(2);
+                }
+                colors.push(color);
+                luminance_squares.push(luminance_square);
+                i += 1;
+            }
+        }
+
+        TileColorsMessage {
+            tile,
+            colors,
+            luminance_squares,
+            samples: samples.iter().map(|s| s.count).collect(),
+        }
+    }
+}
+
+/// The samples a pixel should take next.
+struct PixelSamples {
+    /// The index of the first sample, which is how many the pixel has
+    /// already taken.
+    first: usize,
+    count: usize,
+}
+
+enum TileMessage {
+    /// A tile along with the samples to take for each of its pixels, in
+    /// scanline order within the tile.
+    Tile(Tile, Vec<PixelSamples>),
+    Done,
+}
+
+/// The sums of a tile's samples and how many samples went into each, in
+/// scanline order within the tile.
+struct TileColorsMessage {
+    pub tile: Tile,
+    pub colors: Vec<Color>,
+    pub luminance_squares: Vec<f64>,
+    pub samples: Vec<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::camera::perspective::PerspectiveCamera;
+    use super::super::scene::obj::ObjScene;
+    use super::*;
+
+    const OBJ: &str = "
+v -5 -1 0
+v 5 -1 0
+v 5 -1 -10
+v -5 -1 -10
+v -1 2 -3
+v 1 2 -3
+v 1 2 -5
+v -1 2 -5
+usemtl Floor
+f 1 2 3
+f 1 3 4
+usemtl Light
+f 5 6 7
+f 5 7 8
+";
+
+    const MTL: &str = "
+newmtl Floor
+Ns 0
+Ka 1 1 1
+Kd 0.8 0.8 0.8
+Ks 0 0 0
+d 1
+illum 2
+
+newmtl Light
+Ns 0
+Ka 1 1 1
+Kd 0 0 0
+Ks 0 0 0
+Ke 4 4 4
+d 1
+illum 2
+";
+
+    fn render_with_threads(thread_count: usize) -> Accumulation {
+        let scene: Box<dyn Scene> =
+            Box::new(ObjScene::new(&mut OBJ.as_bytes(), &mut MTL.as_bytes()));
+        let camera = PerspectiveCamera::new();
+        let settings = RenderSettings {
+            samples_per_ray: 4,
+            samples_per_pass: 2,
+            tile_size: 4,
+            seed: Some(7),
+            thread_count,
+            ..RenderSettings::new()
+        };
+
+        let mut accumulation = Accumulation::new(12, 9);
+        render_progressive(&scene, &camera, &mut accumulation, &settings, |_| {
+            Progress::Continue
+        });
+        accumulation
+    }
+
+    /// The mean luminance of a small render of the scene.
+    fn mean_luminance(settings: RenderSettings) -> f64 {
+        let scene: Box<dyn Scene> =
+            Box::new(ObjScene::new(&mut OBJ.as_bytes(), &mut MTL.as_bytes()));
+        let camera = PerspectiveCamera::new();
+        let settings = RenderSettings {
+            tile_size: 4,
+            seed: Some(7),
+            ..settings
+        };
+
+        let mut accumulation = Accumulation::new(8, 6);
+        render_progressive(&scene, &camera, &mut accumulation, &settings, |_| {
+            Progress::Continue
+        });
+
+        let mut total_luminance = 0.0;
+        for y in 0..accumulation.height() {
+            for x in 0..accumulation.width() {
+                total_luminance += accumulation.color(x, y).luminance();
+            }
+        }
+        total_luminance / (accumulation.width() * accumulation.height()) as f64
+    }
+
+    /// Shadow rays towards the light should only take noise out of the
+    /// render, leaving it as bright as bounces alone make it.
+    #[test]
+    fn next_event_estimation_matches_bounces_alone() {
+        let bounces = mean_luminance(RenderSettings {
+            samples_per_ray: 1024,
+            next_event_estimation: false,
+            ..RenderSettings::new()
+        });
+        let shadow_rays = mean_luminance(RenderSettings {
+            samples_per_ray: 1024,
+            ..RenderSettings::new()
+        });
+
+        assert!(bounces > 0.0);
+        assert!((shadow_rays - bounces).abs() < 0.03 * bounces);
+    }
+
+    /// However shadow rays and bounces share the light they both find, it
+    /// should add up to the light that bounces find alone.
+    #[test]
+    fn mis_heuristics_match_bounces_alone() {
+        let bounces = mean_luminance(RenderSettings {
+            samples_per_ray: 1024,
+            next_event_estimation: false,
+            ..RenderSettings::new()
+        });
+
+        for &mis_heuristic in [MisHeuristic::Balance, MisHeuristic::Power].iter() {
+            let combined = mean_luminance(RenderSettings {
+                samples_per_ray: 1024,
+                mis_heuristic,
+                ..RenderSettings::new()
+            });
+            assert!(
+                (combined - bounces).abs() < 0.03 * bounces,
+                "{:?}",
+                mis_heuristic
+            );
+        }
+    }
+
+    /// Ending paths early at random shouldn't dim the render, as long as
+    /// the survivors are brightened to make up for it.
+    #[test]
+    fn roulette_keeps_the_mean() {
+        let full_paths = mean_luminance(RenderSettings {
+            samples_per_ray: 1024,
+            roulette_depth: usize::MAX,
+            ..RenderSettings::new()
+        });
+        let roulette = mean_luminance(RenderSettings {
+            samples_per_ray: 1024,
+            roulette_depth: 0,
+            min_survival_probability: 0.25,
+            max_survival_probability: 0.25,
+            ..RenderSettings::new()
+        });
+
+        assert!(full_paths > 0.0);
+        assert!((roulette - full_paths).abs() < 0.03 * full_paths);
+    }
+
+    #[test]
+    fn seeded_renders_match_across_thread_counts() {
+        let a = render_with_threads(1);
+        let b = render_with_threads(3);
+
+        let mut total_luminance = 0.0;
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let (ca, cb) = (a.color(x, y), b.color(x, y));
+                assert_eq!((ca.r, ca.g, ca.b), (cb.r, cb.g, cb.b));
+                total_luminance += ca.luminance();
+            }
+        }
+        assert!(total_luminance > 0.0);
+    }
+}
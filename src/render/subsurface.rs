@@ -0,0 +1,124 @@
+use super::super::color::{Color, WHITE};
+use super::super::hit::Hit;
+use super::super::material::Material;
+use super::super::medium::Medium;
+use super::super::random::Rng;
+use super::super::ray::Ray;
+use super::super::scene::Scene;
+
+/// Walks that scatter more times than this are taken to have been
+/// absorbed, which loses very little light in all but the palest media.
+const MAX_SCATTERING_EVENTS: usize = 256;
+
+/// Follows light that entered a surface at the ray's origin as it
+/// scatters through the medium beneath it, and returns where it next
+/// reaches a surface along with how much of it does so.
+///
+/// Walks that escape the scene, as through a mesh that isn't closed, or
+/// that scatter too many times return None.
+///
+/// https://jo.dreggn.org/home/2018_manuka.pdf
+pub fn random_walk<'a>(
+    random: &mut Box<dyn Rng>,
+    scene: &'a Box<dyn Scene>,
+    medium: Medium,
+    ray: Ray,
+) -> Option<(Hit, &'a Box<dyn Material>, Color)> {
+    let medium = medium.at_wavelengths(ray.wavelengths);
+    let mut throughput = WHITE;
+    let mut ray = ray;
+
+    for _ in 0..MAX_SCATTERING_EVENTS {
+        let (hit, material) = scene.intersect(ray)?;
+        let hit_distance = hit.position.subtract(ray.origin).length();
+
+        let (distance, weight) = medium.sample_distance(random, throughput, hit_distance);
+        throughput = throughput.multiply(weight);
+        if throughput.luminance() <= 0.0 {
+            return None;
+        }
+
+        let distance = match distance {
+            Some(a) => a,
+            None => return Some((hit, material, throughput)),
+        };
+
+        let position = ray.origin.add(ray.direction.scale(distance));
+        ray = ray.spawn(
+            position,
+            medium.sample_phase(ray.direction, random.next_2d()),
+        );
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::color::BLACK;
+    use super::super::super::random::pcg::Pcg32;
+    use super::super::super::scene::obj::ObjScene;
+    use super::super::super::vector::Vector;
+    use super::*;
+
+    /// A closed slab, 4 wide and deep and 0.2 thick.
+    const OBJ: &str = "
+v -2 -0.1 -2
+v 2 -0.1 -2
+v 2 -0.1 2
+v -2 -0.1 2
+v -2 0.1 -2
+v 2 0.1 -2
+v 2 0.1 2
+v -2 0.1 2
+usemtl Slab
+f 1 3 2
+f 1 4 3
+f 5 6 7
+f 5 7 8
+f 1 2 6
+f 1 6 5
+f 2 3 7
+f 2 7 6
+f 3 4 8
+f 3 8 7
+f 4 1 5
+f 4 5 8
+";
+
+    const MTL: &str = "
+newmtl Slab
+Kd 0.8 0.8 0.8
+";
+
+    /// Light that scatters without being absorbed should all find its way
+    /// back out of the slab, through either of its faces.
+    #[test]
+    fn conserves_energy_without_absorption() {
+        let scene: Box<dyn Scene> =
+            Box::new(ObjScene::new(&mut OBJ.as_bytes(), &mut MTL.as_bytes()));
+        let medium = Medium::new(Color::new(10.0, 20.0, 40.0), BLACK);
+        let mut random: Box<dyn Rng> = Box::new(Pcg32::new(3, 5));
+
+        let walks = 20000;
+        let mut total = BLACK;
+        let (mut reflected, mut transmitted) = (0, 0);
+        for _ in 0..walks {
+            let ray = Ray::new(Vector::new(0.3, 0.1, -0.7), Vector::new(0.0, -1.0, 0.0));
+            if let Some((hit, _, weight)) = random_walk(&mut random, &scene, medium, ray) {
+                total = total.add(weight);
+                if hit.position.y > 0.0 {
+                    reflected += 1;
+                } else {
+                    transmitted += 1;
+                }
+            }
+        }
+
+        let mean = total.scale(1.0 / walks as f64);
+        for &channel in [mean.r, mean.g, mean.b].iter() {
+            assert!((channel - 1.0).abs() < 2e-2, "{}", channel);
+        }
+        assert!(reflected > 0 && transmitted > 0);
+    }
+}
@@ -0,0 +1,141 @@
+/// The order in which tiles are handed out to the render threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    Scanline,
+    /// Outward from the center of the image, which tends to resolve the
+    /// subject of the frame first.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles adjacent to
+    /// each other for better cache locality.
+    Hilbert,
+}
+
+/// A rectangular region of the image, measured in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Divides an image into tiles of at most `tile_size` pixels square and
+/// returns them in the requested order. Tiles along the right and bottom
+/// edges are cropped to fit the image.
+pub fn tiles(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    let coordinates = match order {
+        TileOrder::Scanline => scanline_order(columns, rows),
+        TileOrder::Spiral => spiral_order(columns, rows),
+        TileOrder::Hilbert => hilbert_order(columns, rows),
+    };
+
+    coordinates
+        .iter()
+        .map(|&(column, row)| {
+            let x = column * tile_size;
+            let y = row * tile_size;
+            Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+fn scanline_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let mut coordinates = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            coordinates.push((column, row));
+        }
+    }
+    coordinates
+}
+
+/// Walks a square spiral starting from the center tile, skipping any
+/// steps that fall outside of the grid.
+fn spiral_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = columns * rows;
+    let mut coordinates = Vec::with_capacity(total);
+    if total == 0 {
+        return coordinates;
+    }
+
+    let mut column = ((columns - 1) / 2) as isize;
+    let mut row = ((rows - 1) / 2) as isize;
+    let directions: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut direction = 0;
+    let mut run_length = 1;
+
+    coordinates.push((column as usize, row as usize));
+
+    while coordinates.len() < total {
+        // Each run length is walked twice before it grows by one.
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction];
+            for _ in 0..run_length {
+                column += dx;
+                row += dy;
+                if column >= 0 && row >= 0 && (column as usize) < columns && (row as usize) < rows {
+                    coordinates.push((column as usize, row as usize));
+                }
+            }
+            direction = (direction + 1) % 4;
+        }
+        run_length += 1;
+    }
+
+    coordinates
+}
+
+/// Walks a Hilbert curve over the smallest power-of-two square that
+/// covers the grid, skipping any points that fall outside of it.
+///
+/// https://en.wikipedia.org/wiki/Hilbert_curve#Applications_and_mapping_algorithms
+fn hilbert_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let mut coordinates = Vec::with_capacity(columns * rows);
+    let side = columns.max(rows).next_power_of_two();
+
+    for d in 0..side * side {
+        let (column, row) = hilbert_d2xy(side, d);
+        if column < columns && row < rows {
+            coordinates.push((column, row));
+        }
+    }
+
+    coordinates
+}
+
+fn hilbert_d2xy(side: usize, d: usize) -> (usize, usize) {
+    let mut x = 0;
+    let mut y = 0;
+    let mut t = d;
+    let mut s = 1;
+
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
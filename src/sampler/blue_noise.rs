@@ -0,0 +1,179 @@
+use super::super::random::pcg::Pcg32;
+use super::super::random::{self, Rng};
+use super::{rotate, sobol, u32_to_f64, PixelSample};
+use std::sync::OnceLock;
+
+/// The width and height of the mask, which tiles across the image.
+const SIZE: usize = 32;
+
+/// How far apart, in pixels, points of the mask push each other.
+const SIGMA: f64 = 1.5;
+
+/// The share of the mask set in the initial binary pattern.
+const INITIAL_DENSITY: f64 = 0.1;
+
+/// A single Sobol sequence shared by every pixel, with each pixel's values
+/// rotated by a blue noise mask.
+///
+/// Neighboring pixels get very different rotations, so their error has
+/// little low frequency content and reads as an even grain.
+///
+/// https://belcour.github.io/blog/research/publication/2019/06/17/sampling-bluenoise.html
+pub struct BlueNoiseSampler {
+    seed: u64,
+    x: usize,
+    y: usize,
+    index: u32,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(sample: &PixelSample) -> BlueNoiseSampler {
+        BlueNoiseSampler {
+            seed: sample.seed,
+            x: sample.x,
+            y: sample.y,
+            index: sample.index as u32,
+            dimension: 0,
+        }
+    }
+}
+
+impl Rng for BlueNoiseSampler {
+    fn next_f64(&mut self) -> f64 {
+        let value = u32_to_f64(sobol::scrambled(self.seed, self.index, self.dimension));
+
+        // Each dimension reads the mask from a different place so that the
+        // dimensions stay independent of one another.
+        let shift = random::hash(self.seed, self.dimension);
+        let x = (self.x + shift as usize % SIZE) % SIZE;
+        let y = (self.y + (shift >> 32) as usize % SIZE) % SIZE;
+        self.dimension += 1;
+
+        rotate(value, mask()[y * SIZE + x])
+    }
+}
+
+/// A threshold map where every value in [0, 1) appears once, arranged so
+/// that any threshold gives evenly spaced points.
+fn mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+
+    MASK.get_or_init(|| {
+        let ranks = void_and_cluster();
+        let count = ranks.len() as f64;
+        ranks
+            .into_iter()
+            .map(|rank| (rank as f64 + 0.5) / count)
+            .collect()
+    })
+}
+
+/// Ranks every pixel of the mask by repeatedly filling the largest void
+/// or removing the tightest cluster of a binary pattern.
+///
+/// https://cv.ulichney.com/papers/1993-void-cluster.pdf
+fn void_and_cluster() -> Vec<usize> {
+    let mut random = Pcg32::new(0, 0);
+    let mut pattern = Pattern::new();
+
+    let initial = (SIZE * SIZE) as f64 * INITIAL_DENSITY;
+    while pattern.count < initial as usize {
+        let index = (random.next_f64() * (SIZE * SIZE) as f64) as usize;
+        if !pattern.points[index] {
+            pattern.toggle(index);
+        }
+    }
+
+    // Spread the initial points out until moving the tightest one leaves
+    // it where it was.
+    for _ in 0..SIZE * SIZE {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; SIZE * SIZE];
+    let prototype = pattern.clone();
+
+    while pattern.count > 0 {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        ranks[cluster] = pattern.count;
+    }
+
+    pattern = prototype;
+    while pattern.count < SIZE * SIZE {
+        let void = pattern.largest_void();
+        ranks[void] = pattern.count;
+        pattern.toggle(void);
+    }
+
+    ranks
+}
+
+/// A binary pattern on a torus, along with how crowded every pixel is by
+/// the points set around it.
+#[derive(Clone)]
+struct Pattern {
+    points: Vec<bool>,
+    energy: Vec<f64>,
+    count: usize,
+}
+
+impl Pattern {
+    fn new() -> Pattern {
+        Pattern {
+            points: vec![false; SIZE * SIZE],
+            energy: vec![0.0; SIZE * SIZE],
+            count: 0,
+        }
+    }
+
+    fn toggle(&mut self, index: usize) {
+        let sign = if self.points[index] { -1.0 } else { 1.0 };
+        self.points[index] = !self.points[index];
+        self.count = (self.count as isize + sign as isize) as usize;
+
+        let (x, y) = (index % SIZE, index / SIZE);
+        for (other, energy) in self.energy.iter_mut().enumerate() {
+            let dx = wrapped_distance(x, other % SIZE);
+            let dy = wrapped_distance(y, other / SIZE);
+            *energy += sign * (-((dx * dx + dy * dy) as f64) / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+
+    /// The set point with the most energy.
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    /// The unset point with the least energy.
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, set: bool, better: impl Fn(f64, f64) -> bool) -> usize {
+        let mut best = None;
+        for (index, &point) in self.points.iter().enumerate() {
+            if point != set {
+                continue;
+            }
+            best = match best {
+                Some(b) if !better(self.energy[index], self.energy[b]) => Some(b),
+                _ => Some(index),
+            };
+        }
+        best.unwrap()
+    }
+}
+
+/// The distance between two coordinates of the mask when it wraps around.
+fn wrapped_distance(a: usize, b: usize) -> usize {
+    let distance = a.abs_diff(b);
+    distance.min(SIZE - distance)
+}
@@ -0,0 +1,68 @@
+use super::super::random::pcg::Pcg32;
+use super::super::random::{self, Rng};
+use super::{rotate, u32_to_f64, PixelSample};
+
+/// One base for each dimension the sequence covers. Dimensions past these
+/// fall back to pseudo-random numbers, since high prime bases correlate
+/// badly with one another.
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, where each dimension is the radical inverse of the
+/// sample index in a different prime base.
+///
+/// Every pixel walks the same sequence, so each dimension is rotated by an
+/// offset unique to the pixel.
+///
+/// https://www.pbr-book.org/3ed-2018/Sampling_and_Reconstruction/The_Halton_Sampler
+pub struct HaltonSampler {
+    pixel_seed: u64,
+    index: u64,
+    dimension: usize,
+    fallback: Pcg32,
+}
+
+impl HaltonSampler {
+    pub fn new(sample: &PixelSample) -> HaltonSampler {
+        HaltonSampler {
+            pixel_seed: sample.pixel_seed(),
+            index: sample.index as u64,
+            dimension: 0,
+            fallback: sample.fallback(),
+        }
+    }
+}
+
+impl Rng for HaltonSampler {
+    fn next_f64(&mut self) -> f64 {
+        if self.dimension >= PRIMES.len() {
+            return self.fallback.next_f64();
+        }
+
+        let value = radical_inverse(self.index, PRIMES[self.dimension]);
+        let offset = random::hash(self.pixel_seed, self.dimension as u64) as u32;
+        self.dimension += 1;
+
+        rotate(value, u32_to_f64(offset))
+    }
+}
+
+/// Mirrors the digits of the index in the base around the decimal point.
+///
+/// https://en.wikipedia.org/wiki/Van_der_Corput_sequence
+fn radical_inverse(index: u64, base: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut index = index;
+    let mut digit_weight = inverse_base;
+    let mut value = 0.0;
+
+    while index > 0 {
+        value += (index % base) as f64 * digit_weight;
+        digit_weight *= inverse_base;
+        index /= base;
+    }
+
+    value.min(1.0 - f64::EPSILON)
+}
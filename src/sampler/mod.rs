@@ -0,0 +1,125 @@
+mod blue_noise;
+mod halton;
+mod sobol;
+mod stratified;
+
+use super::random::pcg::Pcg32;
+use super::random::{self, Rng};
+
+/// How the random numbers behind each pixel sample are picked.
+///
+/// Every sampler hands out the dimensions of one sample at a time, in the
+/// order the camera and the path integrator ask for them. Sequences other
+/// than `Random` spread a pixel's samples more evenly across each
+/// dimension than chance alone would, so the image converges with fewer
+/// samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    /// Independent pseudo-random numbers.
+    Random,
+    /// Each dimension is split into as many strata as there are samples
+    /// per pixel and every sample lands in a different one. Raising the
+    /// samples per pixel of a resumed render starts over with new strata.
+    Stratified,
+    /// The Halton sequence, rotated randomly for every pixel.
+    Halton,
+    /// The Sobol sequence with hash-based Owen scrambling for every pixel.
+    Sobol,
+    /// The same Sobol sequence for every pixel, offset by a blue noise
+    /// mask so that the error left between neighboring pixels looks like
+    /// fine grain rather than blotches.
+    BlueNoise,
+}
+
+/// Identifies a single sample of a single pixel.
+#[derive(Clone, Copy)]
+pub struct PixelSample {
+    pub x: usize,
+    pub y: usize,
+    /// The index of the sample within the pixel.
+    pub index: usize,
+    /// How many samples the pixel is expected to receive in total.
+    pub samples_per_pixel: usize,
+    /// The seed of the whole render.
+    pub seed: u64,
+}
+
+impl PixelSample {
+    /// A seed unique to the pixel but shared by all its samples.
+    fn pixel_seed(&self) -> u64 {
+        random::hash(random::hash(self.seed, self.x as u64), self.y as u64)
+    }
+
+    /// A generator unique to the sample, for dimensions that a sequence
+    /// doesn't cover.
+    fn fallback(&self) -> Pcg32 {
+        Pcg32::new(self.pixel_seed(), self.index as u64)
+    }
+}
+
+impl SamplerKind {
+    /// Creates the generator for a single pixel sample.
+    pub fn sampler(&self, sample: &PixelSample) -> Box<dyn Rng> {
+        match self {
+            SamplerKind::Random => Box::new(sample.fallback()),
+            SamplerKind::Stratified => Box::new(stratified::StratifiedSampler::new(sample)),
+            SamplerKind::Halton => Box::new(halton::HaltonSampler::new(sample)),
+            SamplerKind::Sobol => Box::new(sobol::SobolSampler::new(sample)),
+            SamplerKind::BlueNoise => Box::new(blue_noise::BlueNoiseSampler::new(sample)),
+        }
+    }
+}
+
+/// Converts the bits of a fixed-point fraction into a float in [0, 1).
+fn u32_to_f64(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+/// Adds the offset to the value and wraps the result back into [0, 1).
+///
+/// This is a Cranley-Patterson rotation, which keeps a sequence just as
+/// evenly spread while decorrelating it from other pixels.
+fn rotate(value: f64, offset: f64) -> f64 {
+    let rotated = value + offset;
+    if rotated >= 1.0 {
+        return rotated - 1.0;
+    }
+    rotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sequences should spread a pixel's first 16 samples across the
+    /// 16 strata of each of the first few dimensions, one apiece. Halton
+    /// and blue noise are left out, since rotating a sequence shifts its
+    /// strata off of the grid.
+    #[test]
+    fn stratifies_single_dimensions() {
+        let kinds = [SamplerKind::Stratified, SamplerKind::Sobol];
+
+        for kind in kinds.iter() {
+            let mut counts = [[0; 16]; 4];
+            for index in 0..16 {
+                let mut sampler = kind.sampler(&PixelSample {
+                    x: 5,
+                    y: 2,
+                    index,
+                    samples_per_pixel: 16,
+                    seed: 3,
+                });
+                for dimension_counts in counts.iter_mut() {
+                    let value = sampler.next_f64();
+                    assert!((0.0..1.0).contains(&value), "{:?}", kind);
+                    dimension_counts[(value * 16.0) as usize] += 1;
+                }
+            }
+
+            for (dimension, dimension_counts) in counts.iter().enumerate() {
+                let once = dimension_counts.iter().all(|&count| count == 1);
+                assert!(once, "{:?} dimension {}", kind, dimension);
+            }
+        }
+    }
+}
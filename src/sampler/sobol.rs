@@ -0,0 +1,147 @@
+use super::super::random::{self, Rng};
+use super::{u32_to_f64, PixelSample};
+use std::sync::OnceLock;
+
+/// The number of dimensions with their own direction numbers. Later
+/// dimensions reuse these again with a differently shuffled index, which
+/// is known as padding.
+pub const DIMENSIONS: usize = 16;
+
+/// Bits of precision in each value of the sequence.
+const BITS: usize = 32;
+
+/// The degree, the coefficients, and the initial direction numbers of the
+/// primitive polynomial behind each dimension after the first.
+///
+/// https://web.maths.unsw.edu.au/~fkuo/sobol/new-joe-kuo-6.21201
+const POLYNOMIALS: [(usize, u32, [u32; 6]); DIMENSIONS - 1] = [
+    (1, 0, [1, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49]),
+    (6, 13, [1, 1, 1, 15, 21, 21]),
+    (6, 16, [1, 3, 1, 13, 27, 49]),
+];
+
+/// The Sobol sequence with Owen scrambling, unique to every pixel.
+///
+/// Scrambling keeps the sequence stratified across every power of two
+/// samples while making each pixel's points independent of the others.
+///
+/// https://jcgt.org/published/0009/04/01/paper.pdf
+pub struct SobolSampler {
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(sample: &PixelSample) -> SobolSampler {
+        SobolSampler {
+            pixel_seed: sample.pixel_seed(),
+            index: sample.index as u32,
+            dimension: 0,
+        }
+    }
+}
+
+impl Rng for SobolSampler {
+    fn next_f64(&mut self) -> f64 {
+        let value = scrambled(self.pixel_seed, self.index, self.dimension);
+        self.dimension += 1;
+        u32_to_f64(value)
+    }
+}
+
+/// The scrambled value of the dimension for the sample index. The same
+/// seed always gives the same sequence.
+pub fn scrambled(seed: u64, index: u32, dimension: u64) -> u32 {
+    let padding = dimension / DIMENSIONS as u64;
+    let index_seed = random::hash(seed, padding) as u32;
+    let index = nested_uniform_scramble(index, index_seed);
+
+    let value = sobol(index, dimension as usize % DIMENSIONS);
+    let value_seed = random::hash(seed ^ 0x5bd1_e995, dimension) as u32;
+
+    nested_uniform_scramble(value, value_seed)
+}
+
+/// The unscrambled value of a single dimension of the Sobol sequence.
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let directions = &direction_numbers()[dimension];
+    let mut value = 0;
+    let mut index = index;
+    let mut bit = 0;
+
+    while index > 0 {
+        if index & 1 == 1 {
+            value ^= directions[bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+
+    value
+}
+
+/// Expands each dimension's polynomial into one direction number per bit.
+///
+/// https://web.maths.unsw.edu.au/~fkuo/sobol/joe-kuo-notes.pdf
+fn direction_numbers() -> &'static [[u32; BITS]; DIMENSIONS] {
+    static DIRECTIONS: OnceLock<[[u32; BITS]; DIMENSIONS]> = OnceLock::new();
+
+    DIRECTIONS.get_or_init(|| {
+        let mut directions = [[0; BITS]; DIMENSIONS];
+
+        // The first dimension is the van der Corput sequence in base 2.
+        for (bit, direction) in directions[0].iter_mut().enumerate() {
+            *direction = 1 << (BITS - 1 - bit);
+        }
+
+        for (dimension, &(degree, coefficients, initial)) in POLYNOMIALS.iter().enumerate() {
+            let v = &mut directions[dimension + 1];
+
+            for bit in 0..degree.min(BITS) {
+                v[bit] = initial[bit] << (BITS - 1 - bit);
+            }
+
+            for bit in degree..BITS {
+                v[bit] = v[bit - degree] ^ (v[bit - degree] >> degree);
+                for k in 1..degree {
+                    if (coefficients >> (degree - 1 - k)) & 1 == 1 {
+                        v[bit] ^= v[bit - k];
+                    }
+                }
+            }
+        }
+
+        directions
+    })
+}
+
+/// Owen scrambling as a hash: the bits are reversed so that a permutation
+/// in which every bit only depends on the bits below it ends up depending
+/// on the bits above it instead.
+///
+/// https://psychopath.io/post/2021_01_30_building_a_better_lk_hash
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(value: u32, seed: u32) -> u32 {
+    let mut x = value.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
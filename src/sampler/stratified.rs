@@ -0,0 +1,181 @@
+use super::super::random::pcg::Pcg32;
+use super::super::random::{self, Rng};
+use super::PixelSample;
+
+/// Splits every dimension into one stratum per sample and shuffles which
+/// sample lands in which stratum separately for each dimension, which is
+/// also known as Latin hypercube sampling. When the number of samples is
+/// a perfect square, pairs of dimensions are instead stratified across a
+/// jittered grid.
+///
+/// The strata are sized for the number of samples the pixel is expected
+/// to receive. Samples added by resuming a render with more samples per
+/// pixel are stratified among themselves, but not together with those of
+/// the earlier render.
+///
+/// https://graphics.pixar.com/library/MultiJitteredSampling/paper.pdf
+pub struct StratifiedSampler {
+    pixel_seed: u64,
+    /// Which stratum of each dimension the sample falls in before
+    /// shuffling, and the shuffle for samples beyond the expected count.
+    stratum: u32,
+    round: u32,
+    strata: u32,
+    dimension: u64,
+    jitter: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(sample: &PixelSample) -> StratifiedSampler {
+        let strata = sample.samples_per_pixel.max(1) as u32;
+        let index = sample.index as u32;
+
+        StratifiedSampler {
+            pixel_seed: sample.pixel_seed(),
+            stratum: index % strata,
+            round: index / strata,
+            strata,
+            dimension: 0,
+            jitter: sample.fallback(),
+        }
+    }
+
+    fn next_seed(&mut self) -> u32 {
+        let seed = random::hash(
+            random::hash(self.pixel_seed, self.dimension),
+            self.round as u64,
+        );
+        self.dimension += 1;
+        seed as u32
+    }
+}
+
+impl Rng for StratifiedSampler {
+    fn next_f64(&mut self) -> f64 {
+        let seed = self.next_seed();
+        let stratum = permute(self.stratum, self.strata, seed);
+
+        (stratum as f64 + self.jitter.next_f64()) / self.strata as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let side = (self.strata as f64).sqrt() as u32;
+        if side * side != self.strata {
+            return (self.next_f64(), self.next_f64());
+        }
+
+        let seed = self.next_seed();
+        self.dimension += 1;
+        let cell = permute(self.stratum, self.strata, seed);
+
+        let x = (cell % side) as f64 + self.jitter.next_f64();
+        let y = (cell / side) as f64 + self.jitter.next_f64();
+
+        (x / side as f64, y / side as f64)
+    }
+}
+
+/// Shuffles the indexes [0, length) according to the seed, returning
+/// where the index ends up.
+///
+/// https://graphics.pixar.com/library/MultiJitteredSampling/paper.pdf
+/// (listing 3)
+fn permute(index: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    let mut i = index;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < length {
+            break;
+        }
+    }
+
+    (i.wrapping_add(seed)) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws the given dimensions of every sample of a pixel, where true
+    /// stands for a pair of dimensions and false for a single one, whose
+    /// second coordinate is left at 0.
+    fn draw(samples_per_pixel: usize, dimensions: &[bool]) -> Vec<Vec<(f64, f64)>> {
+        (0..samples_per_pixel)
+            .map(|index| {
+                let mut sampler = StratifiedSampler::new(&PixelSample {
+                    x: 3,
+                    y: 7,
+                    index,
+                    samples_per_pixel,
+                    seed: 11,
+                });
+                dimensions
+                    .iter()
+                    .map(|&pair| {
+                        if pair {
+                            sampler.next_2d()
+                        } else {
+                            (sampler.next_f64(), 0.0)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Whether every cell of the grid holds exactly one of the points.
+    fn covers_every_cell(points: &[(f64, f64)], columns: usize, rows: usize) -> bool {
+        let mut counts = vec![0; columns * rows];
+        for &(x, y) in points.iter() {
+            let column = (x * columns as f64) as usize;
+            let row = (y * rows as f64) as usize;
+            counts[row * columns + column] += 1;
+        }
+        counts.iter().all(|&count| count == 1)
+    }
+
+    #[test]
+    fn covers_every_stratum_once() {
+        let dimensions = [false, true, false, true, true, false];
+        let samples = draw(16, &dimensions);
+        for (dimension, &pair) in dimensions.iter().enumerate() {
+            let points: Vec<(f64, f64)> = samples.iter().map(|a| a[dimension]).collect();
+            let (columns, rows) = if pair { (4, 4) } else { (16, 1) };
+            assert!(covers_every_cell(&points, columns, rows), "{}", dimension);
+        }
+
+        // Without a square grid, pairs are stratified one axis at a time.
+        let samples = draw(10, &[false, true]);
+        let points: Vec<(f64, f64)> = samples.iter().map(|a| (a[0].0, 0.0)).collect();
+        assert!(covers_every_cell(&points, 10, 1));
+        let points: Vec<(f64, f64)> = samples.iter().map(|a| (a[1].0, 0.0)).collect();
+        assert!(covers_every_cell(&points, 10, 1));
+        let points: Vec<(f64, f64)> = samples.iter().map(|a| (a[1].1, 0.0)).collect();
+        assert!(covers_every_cell(&points, 10, 1));
+    }
+}
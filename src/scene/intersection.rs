@@ -1,7 +1,31 @@
+use super::super::hit::{Hit, TextureCoordinate};
+use super::super::ray::Ray;
 use super::super::vector::Vector;
 
 pub struct Intersection {
     pub distance_from_origin: f64,
     pub position: Vector,
     pub normal: Vector,
+    pub shading_normal: Vector,
+    pub tangent: Vector,
+    pub bitangent: Vector,
+    /// How much each of a triangle's vertexes weighs in on the position.
+    pub barycentric: [f64; 3],
+    pub uv: TextureCoordinate,
+}
+
+impl Intersection {
+    /// Describes the intersection as a hit by the ray on the shape.
+    pub fn hit(&self, from: Ray, shape_index: usize) -> Hit {
+        Hit {
+            from,
+            position: self.position,
+            normal: self.normal,
+            shading_normal: self.shading_normal,
+            tangent: self.tangent,
+            bitangent: self.bitangent,
+            uv: self.uv,
+            shape_index,
+        }
+    }
 }
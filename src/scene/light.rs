@@ -0,0 +1,143 @@
+use super::super::color::Color;
+use super::super::hit::TextureCoordinate;
+use super::super::random::Rng;
+use super::super::vector::Vector;
+
+/// A point picked on the surface of a light.
+pub struct LightSample {
+    pub position: Vector,
+    pub normal: Vector,
+    pub emittance: Color,
+    pub uv: TextureCoordinate,
+    /// Which of the scene's shapes the point is on.
+    pub shape_index: usize,
+    /// The probability density of having picked this point, with respect
+    /// to surface area across every light in the scene.
+    pub pdf: f64,
+}
+
+/// A triangle that emits light from both of its faces.
+#[derive(Clone, Copy)]
+pub struct TriangleLight {
+    pub vertexes: [Vector; 3],
+    pub uvs: [TextureCoordinate; 3],
+    /// The emittance of the light as a whole, which its texture, if any,
+    /// can vary across its surface.
+    pub emittance: Color,
+    pub shape_index: usize,
+}
+
+impl TriangleLight {
+    fn area(&self) -> f64 {
+        let v0v1 = self.vertexes[1].subtract(self.vertexes[0]);
+        let v0v2 = self.vertexes[2].subtract(self.vertexes[0]);
+        v0v1.cross_product(v0v2).length() / 2.0
+    }
+
+    fn normal(&self) -> Vector {
+        let v0v1 = self.vertexes[0].subtract(self.vertexes[1]);
+        let v0v2 = self.vertexes[0].subtract(self.vertexes[2]);
+        v0v1.cross_product(v0v2).normalize()
+    }
+
+    /// Picks a point uniformly across the surface of the triangle, as the
+    /// weights of each of its vertexes.
+    ///
+    /// https://www.cs.princeton.edu/~funk/tog02.pdf (section 4.2)
+    fn sample_barycentric(&self, random: &mut Box<dyn Rng>) -> [f64; 3] {
+        let (r1, r2) = random.next_2d();
+        let r1 = r1.sqrt();
+
+        [1.0 - r1, r1 * (1.0 - r2), r1 * r2]
+    }
+}
+
+/// Every emissive triangle in a scene, picked in proportion to the power
+/// it emits. Lights of equal brightness are therefore picked in proportion
+/// to their area.
+pub struct LightList {
+    lights: Vec<TriangleLight>,
+    /// The cumulative distribution of the lights' power, normalized so the
+    /// last entry is 1.
+    cdf: Vec<f64>,
+    total_power: f64,
+}
+
+impl LightList {
+    pub fn new(lights: Vec<TriangleLight>) -> LightList {
+        // Lights that emit nothing, or are too small to, could never be
+        // picked, so they're left out.
+        let lights: Vec<TriangleLight> = lights
+            .into_iter()
+            .filter(|light| power(light) > 0.0)
+            .collect();
+        let mut cdf = Vec::with_capacity(lights.len());
+        let mut total_power = 0.0;
+
+        for light in lights.iter() {
+            total_power += power(light);
+            cdf.push(total_power);
+        }
+
+        for value in cdf.iter_mut() {
+            *value /= total_power;
+        }
+
+        LightList {
+            lights,
+            cdf,
+            total_power,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_power <= 0.0
+    }
+
+    /// Picks a light according to its power and then a point across its
+    /// surface.
+    pub fn sample(&self, random: &mut Box<dyn Rng>) -> Option<LightSample> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let u = random.next_f64();
+        let index = self
+            .cdf
+            .partition_point(|value| *value <= u)
+            .min(self.lights.len() - 1);
+
+        let light = &self.lights[index];
+        let weights = light.sample_barycentric(random);
+
+        Some(LightSample {
+            position: light.vertexes[0]
+                .scale(weights[0])
+                .add(light.vertexes[1].scale(weights[1]))
+                .add(light.vertexes[2].scale(weights[2])),
+            normal: light.normal(),
+            emittance: light.emittance,
+            uv: TextureCoordinate::interpolate(light.uvs, weights),
+            shape_index: light.shape_index,
+            pdf: self.pdf(light.emittance),
+        })
+    }
+
+    /// The density with which `sample` picks a point on a light of the
+    /// emittance. Since lights are picked by power, which is their area
+    /// times their brightness, the area cancels out.
+    ///
+    /// Textured lights are still picked by their emittance as a whole, so
+    /// that is what the density depends on.
+    pub fn pdf(&self, emittance: Color) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        emittance.luminance() / self.total_power
+    }
+}
+
+fn power(light: &TriangleLight) -> f64 {
+    light.area() * light.emittance.luminance()
+}
@@ -1,14 +1,33 @@
 mod aabb;
 mod intersection;
+mod light;
 pub mod obj;
 mod tree;
 mod triangle;
 
 use super::hit::Hit;
 use super::material::Material;
+use super::medium::Volume;
+use super::random::Rng;
 use super::ray::Ray;
 
+pub use light::LightSample;
+
 // Scene is a collection of geometry.
 pub trait Scene: Sync {
     fn intersect(&self, ray: Ray) -> Option<(Hit, &Box<dyn Material>)>;
+
+    /// Picks a point on one of the scene's emissive surfaces, or returns
+    /// None when the scene has none.
+    fn sample_light(&self, random: &mut Box<dyn Rng>) -> Option<LightSample>;
+
+    /// The probability density, with respect to surface area, with which
+    /// `sample_light` picks the point that was hit.
+    fn light_pdf(&self, hit: &Hit) -> f64;
+
+    /// The volume filling the space between the scene's surfaces, like
+    /// fog, which the camera sits in.
+    fn volume(&self) -> Option<&Volume> {
+        None
+    }
 }
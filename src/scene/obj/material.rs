@@ -1,69 +1,549 @@
-use super::super::super::color::{Color, BLACK};
-use super::super::super::hit::Hit;
+use super::super::super::color::{Color, BLACK, WHITE};
+use super::super::super::hit::{Hit, TextureCoordinate};
+use super::super::super::material::dielectric::{self, Scatter};
+use super::super::super::material::microfacet::Microfacet;
+use super::super::super::material::principled::Principled;
 use super::super::super::material::{diffuse, specular};
-use super::super::super::material::{Material, MaterialSampler};
+use super::super::super::material::{Bounce, Material};
+use super::super::super::onb::Onb;
 use super::super::super::random::Rng;
-use super::super::super::ray::Ray;
-use wavefront_obj::mtl;
+use super::super::super::spectrum;
+use super::super::super::texture::bump;
+use super::super::super::texture::{
+    find_file, Channel, ColorSpace, ImageTexture, Texture, WrapMode,
+};
+use super::super::super::vector::Vector;
+use super::mtl;
+use std::path::{Path, PathBuf};
+
+/// The index of refraction of coatings and principled surfaces whose MTL
+/// leaves Ni at one, which is typical of varnish, plastics and glass.
+const DEFAULT_INDEX_OF_REFRACTION: f64 = 1.5;
 
 pub struct ObjMaterial {
     source: mtl::Material,
+    /// The roughness of the coating, from Pr when present and otherwise
+    /// from Ns.
+    microfacet: Microfacet,
+    /// Set for materials written with the PBR extension, which replaces
+    /// the coated model.
+    principled: Option<Principled>,
+    texture_diffuse: Option<Box<dyn Texture>>,
+    texture_specular: Option<Box<dyn Texture>>,
+    texture_emissive: Option<Box<dyn Texture>>,
+    texture_dissolve: Option<Box<dyn Texture>>,
+    texture_bump: Option<Box<dyn Texture>>,
+    texture_normal: Option<Box<dyn Texture>>,
+}
+
+impl ObjMaterial {
+    /// Builds the material along with its textures, whose relative paths
+    /// are looked up in each of the search paths in order. Textures that
+    /// can't be loaded are left out, with a warning saying why.
+    pub fn new(
+        source: mtl::Material,
+        search_paths: &[PathBuf],
+        warnings: &mut Vec<String>,
+    ) -> ObjMaterial {
+        let microfacet = match source.roughness {
+            Some(roughness) => Microfacet::from_roughness(roughness),
+            None => Microfacet::from_specular_exponent(source.specular_exponent),
+        };
+
+        let mut load = |map: &Option<mtl::TextureMap>, color_space| {
+            let texture = load_texture(map.as_ref()?, search_paths, color_space);
+            texture.map_err(|warning| warnings.push(warning)).ok()
+        };
+
+        let principled = if source.is_physically_based() {
+            Some(principled(&source))
+        } else {
+            None
+        };
+
+        ObjMaterial {
+            principled,
+            texture_diffuse: load(&source.map_diffuse, ColorSpace::Srgb),
+            texture_specular: load(&source.map_specular, ColorSpace::Srgb),
+            texture_emissive: load(&source.map_emissive, ColorSpace::Srgb),
+            texture_dissolve: load(&source.map_dissolve, ColorSpace::Linear),
+            texture_bump: load(&source.map_bump, ColorSpace::Linear),
+            texture_normal: load(&source.map_normal, ColorSpace::Linear),
+            source,
+            microfacet,
+        }
+    }
 }
 
 impl ObjMaterial {
-    pub fn new(source: mtl::Material) -> ObjMaterial {
-        ObjMaterial { source }
+    /// Kd, multiplied by map_Kd.
+    fn color_diffuse(&self, hit: &Hit) -> Color {
+        match &self.texture_diffuse {
+            Some(texture) => self.source.color_diffuse.multiply(texture.color(hit)),
+            None => self.source.color_diffuse,
+        }
+    }
+
+    /// Ks, multiplied by map_Ks.
+    fn color_specular(&self, hit: &Hit) -> Color {
+        match &self.texture_specular {
+            Some(texture) => self.source.color_specular.multiply(texture.color(hit)),
+            None => self.source.color_specular,
+        }
+    }
+
+    /// The hit's shading normal tilted by norm and then by bump, on the
+    /// side of the surface the hit's ray came from.
+    fn shading_normal(&self, hit: &Hit) -> Vector {
+        let mut hit = *hit;
+
+        if let (Some(texture), Some(map)) = (&self.texture_normal, &self.source.map_normal) {
+            hit.shading_normal = bump::normal_map(texture.as_ref(), &hit, map.bump_multiplier);
+        }
+
+        if let (Some(texture), Some(map)) = (&self.texture_bump, &self.source.map_bump) {
+            hit.shading_normal = bump::bump_map(texture.as_ref(), &hit, map.bump_multiplier);
+        }
+
+        hit.facing_shading_normal()
+    }
+
+    /// The principled material with its base color looked up at the hit,
+    /// along with the hit shaded by the material's normal maps, unless the
+    /// material isn't physically based or is a dielectric.
+    fn principled_at(&self, hit: &Hit) -> Option<(Principled, Hit)> {
+        if self.is_dielectric() {
+            return None;
+        }
+
+        let mut principled = self.principled?;
+        principled.base_color = self.color_diffuse(hit);
+
+        let mut shaded = *hit;
+        shaded.shading_normal = self.shading_normal(hit);
+
+        Some((principled, shaded))
+    }
+
+    fn is_diffuse(&self, hit: &Hit) -> bool {
+        let color = self.color_diffuse(hit);
+        !self.is_dielectric() && (color.r > 0.0 || color.g > 0.0 || color.b > 0.0)
+    }
+
+    fn is_specular(&self, hit: &Hit) -> bool {
+        let color = self.color_specular(hit);
+        !self.is_dielectric() && (color.r > 0.0 || color.g > 0.0 || color.b > 0.0)
+    }
+
+    /// Whether the coating is rough enough to be evaluated rather than
+    /// being a perfect mirror.
+    fn is_glossy(&self, hit: &Hit) -> bool {
+        self.is_specular(hit) && !self.microfacet.is_smooth()
+    }
+
+    /// Whether light passes through the surface, like glass or water. MTL
+    /// illumination models 4, 6 and 7 are the transparent ones, but
+    /// exporters also mark transparency with a dissolve below one. A
+    /// dissolve map instead cuts holes out of an otherwise opaque surface.
+    fn is_dielectric(&self) -> bool {
+        matches!(self.source.illumination, 4 | 6 | 7)
+            || (self.source.dissolve < 1.0 && self.texture_dissolve.is_none())
+    }
+
+    /// The coating is a dielectric with the index of refraction from Ni,
+    /// unless it is left at that of air, in which case it would be
+    /// invisible.
+    fn coating_reflectance(&self, cosine: f64) -> f64 {
+        dielectric::fresnel(cosine, index_of_refraction(&self.source))
+    }
+
+    /// The share of light that passes through the coating at the angle to
+    /// reach the diffuse layer beneath it. The brightest channel of Ks is
+    /// how strongly the coating reflects.
+    fn coating_transmittance(&self, hit: &Hit, cosine: f64) -> f64 {
+        let color_specular = self.color_specular(hit);
+        let strength = color_specular
+            .r
+            .max(color_specular.g)
+            .max(color_specular.b)
+            .min(1.0);
+
+        1.0 - strength * self.coating_reflectance(cosine)
+    }
+
+    /// The chance of bouncing off of the coating rather than the diffuse
+    /// layer, in proportion to how much light each reflects towards the
+    /// hit's ray.
+    fn specular_probability(&self, hit: &Hit, cosine: f64) -> f64 {
+        match (self.is_diffuse(hit), self.is_specular(hit)) {
+            (_, false) => 0.0,
+            (false, true) => 1.0,
+            (true, true) => {
+                let specular =
+                    self.color_specular(hit).luminance() * self.coating_reflectance(cosine);
+                let diffuse =
+                    self.color_diffuse(hit).luminance() * self.coating_transmittance(hit, cosine);
+                if specular + diffuse <= 0.0 {
+                    return 0.0;
+                }
+                specular / (specular + diffuse)
+            }
+        }
+    }
+
+    /// The frame in which the material is evaluated, around the shading
+    /// normal, along with the direction towards where the hit's ray came
+    /// from.
+    ///
+    /// A shading normal tilted far enough would have the ray arriving from
+    /// behind it, so the geometric normal is used instead then.
+    fn local_frame(&self, hit: &Hit) -> (Onb, Vector) {
+        let towards_ray = hit.from.direction.scale(-1.0);
+        let mut normal = self.shading_normal(hit);
+        if normal.dot_product(towards_ray) <= 0.0 {
+            normal = hit.facing_normal();
+        }
+
+        let onb = Onb::from_normal(normal);
+        (onb, onb.local_direction(towards_ray))
+    }
+
+    /// Reflects or refracts the path through the surface.
+    ///
+    /// Light passing into a denser medium is squeezed into a narrower cone
+    /// of directions, which concentrates its radiance by the square of the
+    /// ratio of indexes of refraction. Light leaving is spread back out.
+    ///
+    /// https://www.pbr-book.org/3ed-2018/Light_Transport_III_Bidirectional_Methods/The_Path-Space_Measurement_Equation#Non-symmetryDuetoRefraction
+    fn sample_dielectric(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Bounce {
+        let incident = hit.from.direction;
+        let index_of_refraction = self.source.optical_density.max(1.0);
+
+        // Which side of the surface the ray is on comes from the geometry,
+        // even when the shading normal is tilted.
+        let mut normal = self.shading_normal(hit);
+        if hit.facing_normal().dot_product(hit.normal) < 0.0 {
+            normal = normal.scale(-1.0);
+        }
+
+        match dielectric::scatter(incident, normal, index_of_refraction, random.next_f64()) {
+            Scatter::Reflect(direction) => Bounce {
+                direction,
+                weight: WHITE,
+                pdf: None,
+            },
+            Scatter::Transmit(direction, eta) => Bounce {
+                direction,
+                weight: spectrum::upsample(self.source.transmission_filter, hit.from.wavelengths)
+                    .scale(1.0 / (eta * eta)),
+                pdf: None,
+            },
+        }
     }
 }
 
 impl Material for ObjMaterial {
-    fn sample<'a>(
-        &self,
-        random: &'a mut Box<dyn Rng>,
-        hit: Hit,
-        sampler: Box<dyn MaterialSampler + 'a>,
-    ) -> Color {
-        let mut color = BLACK;
+    fn emittance(&self, hit: &Hit) -> Color {
+        let color = match &self.texture_emissive {
+            Some(texture) => self.source.color_emissive.multiply(texture.color(hit)),
+            None => self.source.color_emissive,
+        };
+        spectrum::upsample(color, hit.from.wavelengths)
+    }
 
-        if self.source.color_diffuse.r > 0.0
-            || self.source.color_diffuse.g > 0.0
-            || self.source.color_diffuse.b > 0.0
+    /// d multiplied by map_d. Without map_d, a d below one makes glass
+    /// instead, as many exporters write it for transparent materials.
+    fn opacity(&self, hit: &Hit) -> f64 {
+        match &self.texture_dissolve {
+            Some(texture) => self.source.dissolve * texture.value(hit),
+            None => 1.0,
+        }
+    }
+
+    /// A diffuse layer with an albedo of Kd beneath a clear coating
+    /// tinted by Ks.
+    ///
+    /// Light reflected by the coating never reaches the diffuse layer, so
+    /// the diffuse term is dimmed by what the coating lets through on the
+    /// way in and again on the way out. That keeps the material from
+    /// reflecting more light than it receives, as simply adding both terms
+    /// would.
+    ///
+    /// A smooth coating is a perfect mirror, which can't be evaluated,
+    /// just like dielectrics.
+    ///
+    /// Light from behind the geometry is blocked even if it is in front of
+    /// the shading normal, so that it can't leak through the surface.
+    ///
+    /// For spectral paths, the sum is upsampled as a whole, which comes
+    /// out the same as upsampling Kd and Ks since upsampling is linear.
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color {
+        if let Some((principled, hit)) = self.principled_at(hit) {
+            return principled.evaluate(&hit, direction);
+        }
+
+        let (onb, outgoing) = self.local_frame(hit);
+        let incoming = onb.local_direction(direction);
+        if outgoing.z <= 0.0
+            || incoming.z <= 0.0
+            || direction.dot_product(hit.facing_normal()) <= 0.0
         {
-            let ray = Ray {
-                origin: hit.position,
-                direction: diffuse::bounce(random, hit.normal),
-            };
+            return BLACK;
+        }
 
-            let color_from_scene = sampler.sample(random, ray);
-            let color_to_camera = color_from_scene.multiply(to_color(self.source.color_diffuse));
+        let mut color = BLACK;
 
-            color = color.add(color_to_camera);
+        if self.is_diffuse(hit) {
+            let transmittance = self.coating_transmittance(hit, outgoing.z)
+                * self.coating_transmittance(hit, incoming.z);
+            color = diffuse::reflectance(self.color_diffuse(hit), onb.w, direction)
+                .scale(transmittance);
         }
 
-        if self.source.color_specular.r > 0.0
-            || self.source.color_specular.g > 0.0
-            || self.source.color_specular.b > 0.0
-        {
-            let ray = Ray {
-                origin: hit.position,
-                direction: specular::bounce(hit.normal, hit.from.direction),
-            };
+        if self.is_glossy(hit) {
+            let half = outgoing.add(incoming).normalize();
+            let reflectance = self.microfacet.reflectance(outgoing, incoming)
+                * self.coating_reflectance(outgoing.dot_product(half));
+            color = color.add(self.color_specular(hit).scale(reflectance));
+        }
+
+        spectrum::upsample(color, hit.from.wavelengths)
+    }
+
+    /// Diffuse bounces are cosine-weighted across the hemisphere, while
+    /// glossy ones reflect off of the facets visible from the hit's ray.
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64 {
+        if self.is_dielectric() {
+            return 0.0;
+        }
+
+        if let Some((principled, hit)) = self.principled_at(hit) {
+            return principled.pdf(&hit, direction);
+        }
+
+        if !self.is_diffuse(hit) && !self.is_specular(hit) {
+            return 0.0;
+        }
 
-            let color_from_scene = sampler.sample(random, ray);
-            let color_to_camera = color_from_scene.multiply(to_color(self.source.color_diffuse));
+        let (onb, outgoing) = self.local_frame(hit);
+        let specular_probability = self.specular_probability(hit, outgoing.z);
+        let mut pdf = (1.0 - specular_probability) * diffuse::pdf(onb.w, direction);
 
-            color = color.add(color_to_camera);
+        if self.is_glossy(hit) {
+            let incoming = onb.local_direction(direction);
+            pdf += specular_probability * self.microfacet.reflection_pdf(outgoing, incoming);
         }
 
-        color = match self.source.color_emissive {
-            Some(color_emissive) => color.add(to_color(color_emissive)),
-            None => color,
+        pdf
+    }
+
+    /// Picks either the diffuse layer or the coating and bounces according
+    /// to it. Dielectrics instead reflect or refract by the Fresnel
+    /// equations, with the index of refraction from Ni.
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        if self.is_dielectric() {
+            return Some(self.sample_dielectric(random, hit));
+        }
+
+        if let Some((principled, hit)) = self.principled_at(hit) {
+            return principled.sample(random, &hit);
+        }
+
+        if !self.is_diffuse(hit) && !self.is_specular(hit) {
+            return None;
+        }
+
+        let (onb, outgoing) = self.local_frame(hit);
+        let specular_probability = self.specular_probability(hit, outgoing.z);
+
+        let direction = if random.next_f64() >= specular_probability {
+            diffuse::bounce(random, onb.w)
+        } else if self.is_glossy(hit) {
+            match self
+                .microfacet
+                .sample_reflection(outgoing, random.next_2d())
+            {
+                Some(incoming) => onb.world_direction(incoming),
+                None => return None,
+            }
+        } else {
+            let reflectance = self.coating_reflectance(outgoing.z);
+            let direction = specular::bounce(onb.w, hit.from.direction);
+            if direction.dot_product(hit.facing_normal()) <= 0.0 {
+                return None;
+            }
+
+            return Some(Bounce {
+                direction,
+                weight: spectrum::upsample(self.color_specular(hit), hit.from.wavelengths)
+                    .scale(reflectance / specular_probability),
+                pdf: None,
+            });
         };
 
-        color
+        if direction.dot_product(hit.facing_normal()) <= 0.0 {
+            return None;
+        }
+
+        // Either term could have picked the direction, so the bounce is
+        // weighed by both of them.
+        let pdf = self.pdf(hit, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(Bounce {
+            direction,
+            weight: self.evaluate(hit, direction).scale(1.0 / pdf),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn interior_keeps_surface(&self) -> bool {
+        self.is_dielectric()
     }
 }
 
-fn to_color(color: mtl::Color) -> Color {
-    Color::new(color.r, color.g, color.b)
+// The index of refraction from Ni, unless it is left at that of air, in
+// which case the surface would be invisible.
+fn index_of_refraction(source: &mtl::Material) -> f64 {
+    if source.optical_density > 1.0 {
+        source.optical_density
+    } else {
+        DEFAULT_INDEX_OF_REFRACTION
+    }
+}
+
+/// Translates the PBR extension's statements into a principled material.
+/// Its base color comes from Kd, and Ns stands in for a missing Pr and
+/// Ni defaults by the same rules as the coated model's.
+fn principled(source: &mtl::Material) -> Principled {
+    let roughness = source.roughness.unwrap_or_else(|| {
+        Microfacet::from_specular_exponent(source.specular_exponent)
+            .alpha_x
+            .sqrt()
+    });
+
+    let defaults = Principled::new();
+
+    Principled {
+        base_color: source.color_diffuse,
+        metallic: source.metallic.unwrap_or(defaults.metallic),
+        roughness,
+        anisotropic: source.anisotropy.unwrap_or(defaults.anisotropic),
+        sheen: source.sheen.unwrap_or(defaults.sheen),
+        clearcoat: source.clearcoat_thickness.unwrap_or(defaults.clearcoat),
+        clearcoat_roughness: source
+            .clearcoat_roughness
+            .unwrap_or(defaults.clearcoat_roughness),
+        index_of_refraction: index_of_refraction(source),
+        emission: source.color_emissive,
+        ..defaults
+    }
+}
+
+/// Opens the map's image from the first search path it is found in, or
+/// returns a warning saying why it can't be.
+fn load_texture(
+    map: &mtl::TextureMap,
+    search_paths: &[PathBuf],
+    color_space: ColorSpace,
+) -> Result<Box<dyn Texture>, String> {
+    let path = find_file(Path::new(&map.path), search_paths);
+
+    let mut texture = ImageTexture::open(&path, color_space)
+        .map_err(|error| format!("skipping texture {}: {}", path.display(), error))?;
+
+    if map.clamp {
+        texture.wrap_mode = WrapMode::Clamp;
+    }
+    texture.channel = match map.channel {
+        Some('r') => Channel::Red,
+        Some('g') => Channel::Green,
+        Some('b') => Channel::Blue,
+        Some('m') => Channel::Alpha,
+        _ => Channel::Luminance,
+    };
+    texture.offset = TextureCoordinate::new(map.offset[0], map.offset[1]);
+    texture.scale = TextureCoordinate::new(map.scale[0], map.scale[1]);
+
+    Ok(Box::new(texture))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::super::random::pcg::Pcg32;
+    use super::super::super::super::ray::Ray;
+    use super::*;
+
+    /// A hit on a surface facing up the z axis, by a ray arriving at the
+    /// angle from its normal.
+    fn hit(cosine: f64) -> Hit {
+        let direction = Vector::new((1.0 - cosine * cosine).sqrt(), 0.0, -cosine);
+        Hit {
+            from: Ray::new(direction.scale(-1.0), direction),
+            position: Vector::zeros(),
+            normal: Vector::new(0.0, 0.0, 1.0),
+            shading_normal: Vector::new(0.0, 0.0, 1.0),
+            tangent: Vector::new(1.0, 0.0, 0.0),
+            bitangent: Vector::new(0.0, 1.0, 0.0),
+            uv: TextureCoordinate::default(),
+            shape_index: 0,
+        }
+    }
+
+    /// Estimates how much of the light arriving from every direction the
+    /// material reflects towards a viewer at the angle from its normal.
+    fn albedo(material: &ObjMaterial, cosine: f64) -> Color {
+        let hit = hit(cosine);
+        let samples = 20_000;
+        let mut random: Box<dyn Rng> = Box::new(Pcg32::new(0, 0));
+        let mut total = BLACK;
+        for _ in 0..samples {
+            if let Some(bounce) = material.sample(&mut random, &hit) {
+                total = total.add(bounce.weight);
+            }
+        }
+
+        total.scale(1.0 / samples as f64)
+    }
+
+    fn material(statements: &str) -> ObjMaterial {
+        let source = format!("newmtl Test\n{}", statements);
+        ObjMaterial::new(mtl::parse(&source).unwrap().remove(0), &[], &mut vec![])
+    }
+
+    /// A white material lit evenly from every direction should reflect no
+    /// more light than reaches it, and a purely diffuse one all of it.
+    #[test]
+    fn white_furnace() {
+        let diffuse = material("Kd 1 1 1");
+        for &cosine in [1.0, 0.5, 0.1].iter() {
+            assert!((albedo(&diffuse, cosine).g - 1.0).abs() < 0.01);
+        }
+
+        for statements in [
+            "Kd 1 1 1\nKs 1 1 1\nNs 10000",
+            "Kd 1 1 1\nKs 1 1 1\nNs 100",
+            "Kd 1 1 1\nKs 1 1 1\nPr 0.3\nNi 2.5",
+        ]
+        .iter()
+        {
+            let layered = material(statements);
+            for &cosine in [1.0, 0.3, 0.05].iter() {
+                assert!(albedo(&layered, cosine).g <= 1.01);
+            }
+        }
+    }
+
+    /// Materials that never bounce diffusely, such as glass or black ones,
+    /// shouldn't claim any density for light sampling to weigh against.
+    #[test]
+    fn pdf_matches_sample() {
+        let direction = Vector::new(0.0, 0.6, 0.8);
+        for &statements in ["illum 7\nNi 1.5", "d 0.5", "Kd 0 0 0\nKs 0 0 0"].iter() {
+            assert_eq!(material(statements).pdf(&hit(0.7), direction), 0.0);
+        }
+        assert!(material("Kd 1 1 1").pdf(&hit(0.7), direction) > 0.0);
+    }
 }
@@ -1,5 +1,6 @@
 mod material;
+mod mtl;
 mod scene;
 mod triangle;
 
-pub use scene::ObjScene;
+pub use scene::{AlphaTest, ObjScene, ObjSceneOptions};
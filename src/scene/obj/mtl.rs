@@ -0,0 +1,400 @@
+use super::super::super::color::{Color, BLACK, WHITE};
+use std::error;
+use std::fmt;
+
+/// A material as described by an MTL file.
+///
+/// Any statement can be left out, in which case it keeps the default set
+/// by `Material::new`.
+///
+/// http://paulbourke.net/dataformats/mtl/
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    /// Ns, the exponent of the Phong specular highlight.
+    pub specular_exponent: f64,
+    /// Pr, the roughness from the PBR extension, which takes precedence
+    /// over Ns when present.
+    pub roughness: Option<f64>,
+    /// Pm, how metallic the material is from the PBR extension.
+    pub metallic: Option<f64>,
+    /// Ps, the sheen from the PBR extension.
+    pub sheen: Option<f64>,
+    /// Pc, the thickness of the clear coat from the PBR extension.
+    pub clearcoat_thickness: Option<f64>,
+    /// Pcr, the roughness of the clear coat from the PBR extension.
+    pub clearcoat_roughness: Option<f64>,
+    /// aniso, the anisotropy from the PBR extension.
+    pub anisotropy: Option<f64>,
+    /// Ka, which is ignored since path tracing accounts for ambient light.
+    pub color_ambient: Color,
+    /// Kd
+    pub color_diffuse: Color,
+    /// Ks
+    pub color_specular: Color,
+    /// Ke
+    pub color_emissive: Color,
+    /// Tf, the color that light passing through the material is filtered
+    /// by.
+    pub transmission_filter: Color,
+    /// Ni, the index of refraction.
+    pub optical_density: f64,
+    /// d, or one minus Tr, which is how opaque the material is.
+    pub dissolve: f64,
+    /// illum, the illumination model from 0 to 10.
+    pub illumination: u32,
+    /// map_Kd
+    pub map_diffuse: Option<TextureMap>,
+    /// map_Ks
+    pub map_specular: Option<TextureMap>,
+    /// map_Ke
+    pub map_emissive: Option<TextureMap>,
+    /// map_d
+    pub map_dissolve: Option<TextureMap>,
+    /// bump or map_Bump, a height map.
+    pub map_bump: Option<TextureMap>,
+    /// norm, a tangent-space normal map.
+    pub map_normal: Option<TextureMap>,
+    /// shader, a nonstandard statement naming a shader graph file that
+    /// replaces the rest of the material's statements.
+    pub shader: Option<String>,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            specular_exponent: 0.0,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            clearcoat_thickness: None,
+            clearcoat_roughness: None,
+            anisotropy: None,
+            color_ambient: BLACK,
+            color_diffuse: BLACK,
+            color_specular: BLACK,
+            color_emissive: BLACK,
+            transmission_filter: WHITE,
+            optical_density: 1.0,
+            dissolve: 1.0,
+            illumination: 2,
+            map_diffuse: None,
+            map_specular: None,
+            map_emissive: None,
+            map_dissolve: None,
+            map_bump: None,
+            map_normal: None,
+            shader: None,
+        }
+    }
+
+    /// Whether the material uses any of the PBR extension's statements
+    /// besides Pr, which also sets the roughness of Ks's coating.
+    ///
+    /// http://exocortex.com/blog/extending_wavefront_mtl_to_support_pbr
+    pub fn is_physically_based(&self) -> bool {
+        self.metallic.is_some()
+            || self.sheen.is_some()
+            || self.clearcoat_thickness.is_some()
+            || self.clearcoat_roughness.is_some()
+            || self.anisotropy.is_some()
+    }
+}
+
+/// An image that varies one of a material's statements across its
+/// surface, along with the options that came before its filename.
+#[derive(Clone)]
+pub struct TextureMap {
+    pub path: String,
+    /// -clamp, which stretches the edges of the image rather than tiling
+    /// it.
+    pub clamp: bool,
+    /// -imfchan, the channel that scalar maps read from: r, g, b, m for
+    /// the matte or alpha channel, l for luminance or z for depth.
+    pub channel: Option<char>,
+    /// -o, which shifts the image's u and v coordinates.
+    pub offset: [f64; 2],
+    /// -s, which stretches the image's u and v coordinates.
+    pub scale: [f64; 2],
+    /// -bm, which multiplies the height of bump maps and the tilt of
+    /// normal maps.
+    pub bump_multiplier: f64,
+}
+
+impl TextureMap {
+    pub fn new(path: &str) -> TextureMap {
+        TextureMap {
+            path: path.to_string(),
+            clamp: false,
+            channel: None,
+            offset: [0.0, 0.0],
+            scale: [1.0, 1.0],
+            bump_multiplier: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Reads every material in an MTL file.
+///
+/// Unlike stricter parsers, statements may come in any order and ones that
+/// aren't understood are skipped, since exporters each write their own
+/// subset and extensions of the format.
+pub fn parse(input: &str) -> Result<Vec<Material>, ParseError> {
+    let mut materials: Vec<Material> = vec![];
+
+    for (i, line) in input.lines().enumerate() {
+        let error = |message: String| ParseError {
+            line: i + 1,
+            message,
+        };
+
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(a) => a,
+            None => continue,
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        if keyword == "newmtl" {
+            materials.push(Material::new(&arguments.join(" ")));
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(a) => a,
+            None => return Err(error(format!("{} comes before any newmtl", keyword))),
+        };
+
+        match keyword {
+            "Ns" => material.specular_exponent = parse_number(&arguments).map_err(error)?,
+            "Pr" => material.roughness = Some(parse_number(&arguments).map_err(error)?),
+            "Pm" => material.metallic = Some(parse_number(&arguments).map_err(error)?),
+            "Ps" => material.sheen = Some(parse_number(&arguments).map_err(error)?),
+            "Pc" => material.clearcoat_thickness = Some(parse_number(&arguments).map_err(error)?),
+            "Pcr" => material.clearcoat_roughness = Some(parse_number(&arguments).map_err(error)?),
+            "aniso" => material.anisotropy = Some(parse_number(&arguments).map_err(error)?),
+            "Ka" => material.color_ambient = parse_color(&arguments).map_err(error)?,
+            "Kd" => material.color_diffuse = parse_color(&arguments).map_err(error)?,
+            "Ks" => material.color_specular = parse_color(&arguments).map_err(error)?,
+            "Ke" => material.color_emissive = parse_color(&arguments).map_err(error)?,
+            "Tf" => material.transmission_filter = parse_color(&arguments).map_err(error)?,
+            "Ni" => material.optical_density = parse_number(&arguments).map_err(error)?,
+            "d" => material.dissolve = parse_number(&arguments).map_err(error)?,
+            "Tr" => material.dissolve = 1.0 - parse_number(&arguments).map_err(error)?,
+            "illum" => material.illumination = parse_integer(&arguments).map_err(error)?,
+            "map_Kd" => material.map_diffuse = Some(parse_texture_map(&arguments).map_err(error)?),
+            "map_Ks" => material.map_specular = Some(parse_texture_map(&arguments).map_err(error)?),
+            "map_Ke" => material.map_emissive = Some(parse_texture_map(&arguments).map_err(error)?),
+            "map_d" => material.map_dissolve = Some(parse_texture_map(&arguments).map_err(error)?),
+            "bump" | "map_Bump" | "map_bump" => {
+                material.map_bump = Some(parse_texture_map(&arguments).map_err(error)?)
+            }
+            "norm" => material.map_normal = Some(parse_texture_map(&arguments).map_err(error)?),
+            "shader" => material.shader = Some(arguments.join(" ")),
+            _ => continue,
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Reads the last argument as a number, skipping options such as the
+/// `-halo` of `d -halo 0.5`.
+fn parse_number(arguments: &[&str]) -> Result<f64, String> {
+    let argument = match arguments.last() {
+        Some(a) => a,
+        None => return Err("missing a number".to_string()),
+    };
+
+    parse_finite(argument).ok_or_else(|| format!("{} is not a number", argument))
+}
+
+/// Reads the last argument as a whole number that isn't negative.
+fn parse_integer(arguments: &[&str]) -> Result<u32, String> {
+    let argument = match arguments.last() {
+        Some(a) => a,
+        None => return Err("missing a number".to_string()),
+    };
+
+    argument
+        .parse()
+        .map_err(|_| format!("{} is not a whole number", argument))
+}
+
+/// Reads a number, which neither infinities nor NaN are.
+fn parse_finite(argument: &str) -> Option<f64> {
+    match argument.parse::<f64>() {
+        Ok(value) if value.is_finite() => Some(value),
+        _ => None,
+    }
+}
+
+/// Reads an RGB color. A single value stands for a shade of gray.
+fn parse_color(arguments: &[&str]) -> Result<Color, String> {
+    let mut values = vec![];
+    for argument in arguments.iter() {
+        match parse_finite(argument) {
+            Some(a) => values.push(a),
+            None => return Err(format!("{} is not an RGB color", arguments.join(" "))),
+        }
+    }
+
+    match values.len() {
+        1 => Ok(Color::new(values[0], values[0], values[0])),
+        3 => Ok(Color::new(values[0], values[1], values[2])),
+        _ => Err(format!("{} is not an RGB color", arguments.join(" "))),
+    }
+}
+
+/// Reads a texture's options followed by its filename, which may contain
+/// spaces.
+fn parse_texture_map(arguments: &[&str]) -> Result<TextureMap, String> {
+    let mut texture_map = TextureMap::new("");
+    let mut i = 0;
+
+    while i < arguments.len() && arguments[i].starts_with('-') {
+        let option = arguments[i];
+        i += 1;
+
+        // Options take a fixed number of arguments, except for those that
+        // take up to three numbers.
+        let count = match option {
+            "-blendu" | "-blendv" | "-bm" | "-boost" | "-cc" | "-clamp" | "-imfchan"
+            | "-texres" => 1,
+            "-mm" => 2,
+            "-o" | "-s" | "-t" => arguments[i..]
+                .iter()
+                .take(3)
+                .take_while(|a| a.parse::<f64>().is_ok())
+                .count(),
+            _ => return Err(format!("{} is not a texture option", option)),
+        };
+
+        if i + count > arguments.len() {
+            return Err(format!("{} is missing arguments", option));
+        }
+        let values = &arguments[i..i + count];
+        i += count;
+
+        match option {
+            "-clamp" => texture_map.clamp = values[0] == "on",
+            "-imfchan" => texture_map.channel = values[0].chars().next(),
+            "-o" => texture_map.offset = parse_coordinate(values, 0.0)?,
+            "-s" => texture_map.scale = parse_coordinate(values, 1.0)?,
+            "-bm" => texture_map.bump_multiplier = parse_number(values)?,
+            _ => continue,
+        }
+    }
+
+    if i >= arguments.len() {
+        return Err("missing a filename".to_string());
+    }
+    texture_map.path = arguments[i..].join(" ");
+
+    Ok(texture_map)
+}
+
+/// Reads the u and v of an option, with v left at the default if missing.
+fn parse_coordinate(values: &[&str], default: f64) -> Result<[f64; 2], String> {
+    let mut coordinate = [default, default];
+    for (value, argument) in coordinate.iter_mut().zip(values.iter()) {
+        *value = parse_finite(argument).ok_or_else(|| format!("{} is not a number", argument))?;
+    }
+
+    Ok(coordinate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_statements_in_any_order() {
+        let materials = parse(
+            "# A comment\n\
+             newmtl Glass\n\
+             illum 7\n\
+             Ni 1.5 # Crown glass\n\
+             Kd 0.5\n\
+             Tr 0.25\n\
+             map_Kd glass.png\n\
+             \n\
+             newmtl Light Bulb\n\
+             Ke 1 2 3\n",
+        )
+        .unwrap();
+
+        assert_eq!(materials.len(), 2);
+
+        assert_eq!(materials[0].name, "Glass");
+        assert_eq!(materials[0].illumination, 7);
+        assert_eq!(materials[0].optical_density, 1.5);
+        assert_eq!(materials[0].color_diffuse.g, 0.5);
+        assert_eq!(materials[0].dissolve, 0.75);
+
+        assert_eq!(materials[1].name, "Light Bulb");
+        assert_eq!(materials[1].color_emissive.b, 3.0);
+        assert_eq!(materials[1].dissolve, 1.0);
+    }
+
+    #[test]
+    fn parses_texture_options() {
+        let materials = parse(
+            "newmtl Leaves\n\
+             map_Kd -s 2 4 1 -clamp on leaves diffuse.png\n\
+             map_d -imfchan m -o 0.5 -bm 1 leaves.png\n\
+             map_Bump -bm 0.25 leaves height.png\n",
+        )
+        .unwrap();
+
+        let map_diffuse = materials[0].map_diffuse.as_ref().unwrap();
+        assert_eq!(map_diffuse.path, "leaves diffuse.png");
+        assert_eq!(map_diffuse.scale, [2.0, 4.0]);
+        assert!(map_diffuse.clamp);
+
+        let map_dissolve = materials[0].map_dissolve.as_ref().unwrap();
+        assert_eq!(map_dissolve.path, "leaves.png");
+        assert_eq!(map_dissolve.channel, Some('m'));
+        assert_eq!(map_dissolve.offset, [0.5, 0.0]);
+        assert!(!map_dissolve.clamp);
+
+        let map_bump = materials[0].map_bump.as_ref().unwrap();
+        assert_eq!(map_bump.path, "leaves height.png");
+        assert_eq!(map_bump.bump_multiplier, 0.25);
+    }
+
+    #[test]
+    fn reports_the_line_of_malformed_statements() {
+        let error = match parse("newmtl A\nKd 1 2\n") {
+            Ok(_) => panic!("expected an error"),
+            Err(a) => a,
+        };
+
+        assert_eq!(error.line, 2);
+
+        for statement in ["Kd nan 0 0", "Ns inf", "d -inf", "illum -1", "illum 2.7"].iter() {
+            let source = format!("newmtl A\n{}\n", statement);
+            assert!(parse(&source).is_err(), "{}", statement);
+        }
+    }
+}
@@ -1,56 +1,189 @@
-use super::super::super::hit::Hit;
+use super::super::super::color::Color;
+use super::super::super::constants::EPSILON;
+use super::super::super::hit::{Hit, TextureCoordinate};
+use super::super::super::material::measured::MeasuredBrdf;
+use super::super::super::material::subsurface::Subsurface;
+use super::super::super::material::volume_boundary::VolumeBoundary;
 use super::super::super::material::Material;
+use super::super::super::medium::Volume;
+use super::super::super::random::{self, Rng};
 use super::super::super::ray::Ray;
+use super::super::super::shader::ShaderGraph;
+use super::super::super::texture::find_file;
 use super::super::super::vector::Vector;
 use super::super::aabb::AABB;
+use super::super::intersection::Intersection;
+use super::super::light::{LightList, LightSample, TriangleLight};
 use super::super::tree;
 use super::super::Scene;
 use super::material::ObjMaterial;
+use super::mtl;
 use super::triangle::ObjTriangle;
 use std::collections;
 use std::f64::{MAX, MIN};
+use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
 use wavefront_obj;
 
+/// Settings for how an OBJ scene is loaded.
+#[derive(Clone, Default)]
+pub struct ObjSceneOptions {
+    /// The directories that the relative paths of textures are looked up
+    /// in, in order.
+    pub texture_search_paths: Vec<PathBuf>,
+    /// Smooths triangles without normals of their own by averaging the
+    /// normals of their neighbors, which are those sharing a vertex and
+    /// lying within this many degrees of each other. Edges at sharper
+    /// angles stay creased. Left as None, such triangles are flat.
+    pub crease_angle: Option<f64>,
+    /// How rays pass through surfaces whose materials cut them out.
+    pub alpha_test: AlphaTest,
+    /// The volume filling the space around the scene's meshes, like fog.
+    pub volume: Option<Volume>,
+    /// Fills the closed meshes of the named materials with volumes.
+    /// Transparent materials keep their surfaces, like water behind glass,
+    /// while the surfaces of others vanish, leaving smoke or a bank of
+    /// fog.
+    pub volumes: collections::HashMap<String, Volume>,
+    /// Replaces the named materials with BRDFs measured from real ones,
+    /// loaded from MERL .binary files or tabulated .csv ones. Relative
+    /// paths are looked up like those of textures.
+    pub measured_materials: collections::HashMap<String, PathBuf>,
+    /// Replaces the named materials with translucent ones, like skin, wax
+    /// or marble, that light scatters beneath the surface of. They take
+    /// their color from Kd, and light travels the given mean free path in
+    /// each channel, in the scene's units. Their meshes should be closed.
+    pub subsurface_materials: collections::HashMap<String, Color>,
+}
+
+/// How partially opaque surfaces, such as leaves textured onto quads, are
+/// cut out as rays are traced through the scene. Shadow rays are cut out
+/// the same way, so holes let light through as well.
+#[derive(Clone, Copy, Default)]
+pub enum AlphaTest {
+    /// Rays pass through wherever the opacity is below the threshold,
+    /// which keeps the edges of cut outs crisp.
+    Threshold(f64),
+    /// Rays pass through with a probability of one minus the opacity,
+    /// which renders partial transparency faithfully at the cost of
+    /// noise.
+    #[default]
+    Stochastic,
+}
+
 pub struct ObjScene {
+    lights: LightList,
     materials: Vec<Box<dyn Material>>,
+    /// The Ke of each material, which lights are picked by.
+    material_emittances: Vec<Color>,
     tree: Box<tree::TreeNode>,
     tree_shapes: Vec<Box<dyn tree::TreeShape>>,
     tree_shape_material_indexes: Vec<usize>,
+    alpha_test: AlphaTest,
+    volume: Option<Volume>,
+    warnings: Vec<String>,
 }
 
 unsafe impl Sync for ObjScene {}
 
 impl ObjScene {
     pub fn new(obj: &mut impl io::Read, mtl: &mut impl io::Read) -> ObjScene {
+        ObjScene::with_options(obj, mtl, &ObjSceneOptions::default()).unwrap()
+    }
+
+    /// Loads the scene from files, looking for textures next to the MTL
+    /// file before the options' search paths.
+    pub fn open(
+        obj_path: &Path,
+        mtl_path: &Path,
+        options: &ObjSceneOptions,
+    ) -> io::Result<ObjScene> {
+        let mut obj_file = fs::File::open(obj_path)?;
+        let mut mtl_file = fs::File::open(mtl_path)?;
+
+        let mut options = options.clone();
+        if let Some(directory) = mtl_path.parent() {
+            options
+                .texture_search_paths
+                .insert(0, directory.to_path_buf());
+        }
+
+        ObjScene::with_options(&mut obj_file, &mut mtl_file, &options)
+    }
+
+    /// Loads the scene, failing with `InvalidData` if either the OBJ or the
+    /// MTL is malformed.
+    pub fn with_options(
+        obj: &mut impl io::Read,
+        mtl: &mut impl io::Read,
+        options: &ObjSceneOptions,
+    ) -> io::Result<ObjScene> {
         let mut obj_string = String::new();
         let mut mtl_string = String::new();
 
-        obj.read_to_string(&mut obj_string).unwrap();
-        let obj_set = wavefront_obj::obj::parse(obj_string).unwrap();
+        obj.read_to_string(&mut obj_string)?;
+        let obj_set = wavefront_obj::obj::parse(obj_string).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("OBJ line {}: {}", error.line_number, error.message),
+            )
+        })?;
 
-        mtl.read_to_string(&mut mtl_string).unwrap();
-        let mtl_set = wavefront_obj::mtl::parse(mtl_string).unwrap();
+        mtl.read_to_string(&mut mtl_string)?;
+        let mtl_materials = mtl::parse(&mtl_string).map_err(|error| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("MTL {}", error))
+        })?;
 
+        let mut warnings = vec![];
         let mut materials: Vec<Box<dyn Material>> = vec![];
+        let mut material_emittances = vec![];
         let mut material_indexes = collections::HashMap::new();
-        for (i, material) in mtl_set.materials.iter().enumerate() {
-            materials.push(Box::new(ObjMaterial::new(material.clone())));
+        for (i, material) in mtl_materials.iter().enumerate() {
+            let mut loaded = load_material(material, options, &mut warnings);
+            if let Some(volume) = options.volumes.get(&material.name) {
+                let surface = if loaded.interior_keeps_surface() {
+                    Some(loaded)
+                } else {
+                    None
+                };
+                loaded = Box::new(VolumeBoundary::new(surface, volume.clone()));
+            }
+            materials.push(loaded);
+            material_emittances.push(material.color_emissive);
             material_indexes.insert(material.name.clone(), i);
         }
 
         let mut tree_shapes: Vec<Box<dyn tree::TreeShape>> = vec![];
         let mut tree_shape_indexes = vec![];
         let mut tree_shape_material_indexes = vec![];
+        let mut lights = vec![];
         let mut aabb_min = Vector::new(MAX, MAX, MAX);
         let mut aabb_max = Vector::new(MIN, MIN, MIN);
 
         let mut i = 0;
 
+        let crease_cosine = options
+            .crease_angle
+            .map_or(1.0, |angle| angle.to_radians().cos());
+
         for object in obj_set.objects.iter() {
+            let vertex_face_normals = options
+                .crease_angle
+                .map(|_| collect_vertex_face_normals(object));
+
             for geometry in object.geometry.iter() {
-                let material_name = geometry.material_name.clone().unwrap();
-                let material_index = material_indexes.get(&material_name).unwrap();
+                let material_index = geometry
+                    .material_name
+                    .as_ref()
+                    .and_then(|name| material_indexes.get(name))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{} uses a material the MTL lacks", object.name),
+                        )
+                    })?;
+                let emittance = mtl_materials[*material_index].color_emissive;
 
                 for shape in geometry.shapes.iter() {
                     match shape.primitive {
@@ -75,34 +208,83 @@ impl ObjScene {
                                 ),
                             ];
 
-                            let normals: [Vector; 3];
+                            let face_normal = face_normal(vertexes);
 
-                            if v0.2.is_some() && v1.2.is_some() && v2.2.is_some() {
-                                normals = [
+                            let normals = match (v0.2, v1.2, v2.2) {
+                                (Some(n0), Some(n1), Some(n2)) => [
                                     Vector::new(
-                                        object.vertices[v0.2.unwrap()].x,
-                                        object.vertices[v0.2.unwrap()].y,
-                                        object.vertices[v0.2.unwrap()].z,
+                                        object.normals[n0].x,
+                                        object.normals[n0].y,
+                                        object.normals[n0].z,
                                     ),
                                     Vector::new(
-                                        object.vertices[v1.2.unwrap()].x,
-                                        object.vertices[v1.2.unwrap()].y,
-                                        object.vertices[v1.2.unwrap()].z,
+                                        object.normals[n1].x,
+                                        object.normals[n1].y,
+                                        object.normals[n1].z,
                                     ),
                                     Vector::new(
-                                        object.vertices[v2.2.unwrap()].x,
-                                        object.vertices[v2.2.unwrap()].y,
-                                        object.vertices[v2.2.unwrap()].z,
+                                        object.normals[n2].x,
+                                        object.normals[n2].y,
+                                        object.normals[n2].z,
                                     ),
-                                ];
-                            } else {
-                                let v0v1 = vertexes[0].subtract(vertexes[1]);
-                                let v0v2 = vertexes[0].subtract(vertexes[2]);
-                                let normal = v0v1.cross_product(v0v2).normalize();
-                                normals = [normal, normal, normal];
-                            }
+                                ],
+                                _ => match &vertex_face_normals {
+                                    Some(vertex_face_normals) => [
+                                        smooth_normal(
+                                            &vertex_face_normals[v0.0],
+                                            face_normal,
+                                            crease_cosine,
+                                        ),
+                                        smooth_normal(
+                                            &vertex_face_normals[v1.0],
+                                            face_normal,
+                                            crease_cosine,
+                                        ),
+                                        smooth_normal(
+                                            &vertex_face_normals[v2.0],
+                                            face_normal,
+                                            crease_cosine,
+                                        ),
+                                    ],
+                                    None => {
+                                        let normal = face_normal.normalize();
+                                        [normal, normal, normal]
+                                    }
+                                },
+                            };
+
+                            let uvs = match (v0.1, v1.1, v2.1) {
+                                (Some(t0), Some(t1), Some(t2)) => [
+                                    TextureCoordinate::new(
+                                        object.tex_vertices[t0].u,
+                                        object.tex_vertices[t0].v,
+                                    ),
+                                    TextureCoordinate::new(
+                                        object.tex_vertices[t1].u,
+                                        object.tex_vertices[t1].v,
+                                    ),
+                                    TextureCoordinate::new(
+                                        object.tex_vertices[t2].u,
+                                        object.tex_vertices[t2].v,
+                                    ),
+                                ],
+                                _ => [
+                                    TextureCoordinate::new(0.0, 0.0),
+                                    TextureCoordinate::new(1.0, 0.0),
+                                    TextureCoordinate::new(1.0, 1.0),
+                                ],
+                            };
 
-                            let obj_triangle = ObjTriangle { normals, vertexes };
+                            let obj_triangle = ObjTriangle::new(vertexes, normals, uvs);
+
+                            if emittance.luminance() > 0.0 {
+                                lights.push(TriangleLight {
+                                    vertexes: obj_triangle.vertexes,
+                                    uvs: obj_triangle.uvs,
+                                    emittance,
+                                    shape_index: i,
+                                });
+                            }
 
                             tree_shapes.push(Box::new(obj_triangle));
                             tree_shape_indexes.push(i);
@@ -125,20 +307,203 @@ impl ObjScene {
 
         let root_aabb = AABB::new(aabb_min, aabb_max);
 
-        ObjScene {
+        Ok(ObjScene {
+            lights: LightList::new(lights),
             materials,
+            material_emittances,
             tree: tree::build_tree_node(&tree_shapes, &tree_shape_indexes, root_aabb).unwrap(),
             tree_shapes,
             tree_shape_material_indexes,
+            alpha_test: options.alpha_test,
+            volume: options.volume.clone(),
+            warnings,
+        })
+    }
+
+    /// What couldn't be loaded and was left out of the scene, such as
+    /// missing textures, for the caller to report.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Whether the ray stops at the intersection, or passes through since
+    /// the material is cut out there.
+    ///
+    /// Stochastic tests draw their odds from a hash of the ray and the
+    /// position, since rays are traced without a source of random numbers.
+    /// The same ray therefore always meets the same surfaces.
+    fn is_opaque(&self, ray: Ray, shape_index: usize, intersection: &Intersection) -> bool {
+        let material_index = self.tree_shape_material_indexes[shape_index];
+        let opacity = self.materials[material_index].opacity(&intersection.hit(ray, shape_index));
+        if opacity >= 1.0 {
+            return true;
+        }
+
+        match self.alpha_test {
+            AlphaTest::Threshold(threshold) => opacity >= threshold,
+            AlphaTest::Stochastic => {
+                let seed = [
+                    ray.origin.x,
+                    ray.origin.y,
+                    ray.origin.z,
+                    ray.direction.x,
+                    ray.direction.y,
+                    ray.direction.z,
+                    intersection.distance_from_origin,
+                ]
+                .iter()
+                .fold(shape_index as u64, |seed, value| {
+                    random::hash(seed, value.to_bits())
+                });
+
+                ((seed >> 11) as f64 / (1u64 << 53) as f64) < opacity
+            }
         }
     }
 }
 
 impl Scene for ObjScene {
     fn intersect(&self, ray: Ray) -> Option<(Hit, &Box<dyn Material>)> {
-        tree::intersect_tree_node(&self.tree_shapes, &self.tree, ray).map(|intersection| {
+        let accept = |shape_index: usize, intersection: &Intersection| {
+            self.is_opaque(ray, shape_index, intersection)
+        };
+
+        tree::intersect_tree_node(&self.tree_shapes, &self.tree, ray, &accept).map(|intersection| {
             let material_index = self.tree_shape_material_indexes[intersection.nearest_shape_index];
             (intersection.hit, &self.materials[material_index])
         })
     }
+
+    /// Lights are picked by their Ke alone, but the light from the picked
+    /// point is looked up from its material in case it is textured.
+    fn sample_light(&self, random: &mut Box<dyn Rng>) -> Option<LightSample> {
+        self.lights.sample(random).map(|mut light| {
+            let hit = Hit {
+                from: Ray::new(light.position.add(light.normal), light.normal.scale(-1.0)),
+                position: light.position,
+                normal: light.normal,
+                shading_normal: light.normal,
+                tangent: Vector::zeros(),
+                bitangent: Vector::zeros(),
+                uv: light.uv,
+                shape_index: light.shape_index,
+            };
+            let material_index = self.tree_shape_material_indexes[light.shape_index];
+            light.emittance = self.materials[material_index].emittance(&hit);
+            light
+        })
+    }
+
+    fn light_pdf(&self, hit: &Hit) -> f64 {
+        let material_index = self.tree_shape_material_indexes[hit.shape_index];
+        self.lights.pdf(self.material_emittances[material_index])
+    }
+
+    fn volume(&self) -> Option<&Volume> {
+        self.volume.as_ref()
+    }
+}
+
+/// Loads the measured BRDF or the subsurface material that overrides the
+/// MTL material, or else its shader graph if it names one, falling back on
+/// its other statements if none of them apply or can be loaded. Whatever
+/// fails to load is added to the warnings.
+fn load_material(
+    material: &mtl::Material,
+    options: &ObjSceneOptions,
+    warnings: &mut Vec<String>,
+) -> Box<dyn Material> {
+    let search_paths = &options.texture_search_paths;
+
+    if let Some(path) = options.measured_materials.get(&material.name) {
+        let path = find_file(path, search_paths);
+        match MeasuredBrdf::open(&path) {
+            Ok(brdf) => return Box::new(brdf),
+            Err(error) => warnings.push(format!(
+                "skipping measured BRDF {}: {}",
+                path.display(),
+                error
+            )),
+        }
+    }
+
+    if let Some(&mean_free_path) = options.subsurface_materials.get(&material.name) {
+        return Box::new(Subsurface::new(material.color_diffuse, mean_free_path));
+    }
+
+    if let Some(shader) = &material.shader {
+        let path = find_file(Path::new(shader), search_paths);
+        match ShaderGraph::open(&path, search_paths) {
+            Ok(graph) => return Box::new(graph),
+            Err(error) => warnings.push(format!("skipping shader {}: {}", path.display(), error)),
+        }
+    }
+
+    Box::new(ObjMaterial::new(material.clone(), search_paths, warnings))
+}
+
+/// The normal of the triangle's plane, whose length is twice its area.
+fn face_normal(vertexes: [Vector; 3]) -> Vector {
+    let v0v1 = vertexes[0].subtract(vertexes[1]);
+    let v0v2 = vertexes[0].subtract(vertexes[2]);
+    v0v1.cross_product(v0v2)
+}
+
+/// Gathers the face normals of every triangle in the object by the
+/// vertexes they share.
+fn collect_vertex_face_normals(object: &wavefront_obj::obj::Object) -> Vec<Vec<Vector>> {
+    let mut vertex_face_normals = vec![vec![]; object.vertices.len()];
+
+    for geometry in object.geometry.iter() {
+        for shape in geometry.shapes.iter() {
+            if let wavefront_obj::obj::Primitive::Triangle(v0, v1, v2) = shape.primitive {
+                let vertexes = [v0.0, v1.0, v2.0];
+                let normal = face_normal([
+                    Vector::new(
+                        object.vertices[v0.0].x,
+                        object.vertices[v0.0].y,
+                        object.vertices[v0.0].z,
+                    ),
+                    Vector::new(
+                        object.vertices[v1.0].x,
+                        object.vertices[v1.0].y,
+                        object.vertices[v1.0].z,
+                    ),
+                    Vector::new(
+                        object.vertices[v2.0].x,
+                        object.vertices[v2.0].y,
+                        object.vertices[v2.0].z,
+                    ),
+                ]);
+                for &vertex in vertexes.iter() {
+                    vertex_face_normals[vertex].push(normal);
+                }
+            }
+        }
+    }
+
+    vertex_face_normals
+}
+
+/// Averages the face normals around a vertex that are within the crease
+/// angle of the triangle's own, which is among them. Larger triangles
+/// weigh in more, since face normals are as long as their triangles are
+/// large.
+fn smooth_normal(face_normals: &[Vector], face_normal: Vector, crease_cosine: f64) -> Vector {
+    let direction = face_normal.normalize();
+    let mut normal = Vector::zeros();
+
+    for &neighbor in face_normals.iter() {
+        let length = neighbor.length();
+        if length < EPSILON {
+            continue;
+        }
+
+        let cosine = direction.dot_product(neighbor.scale(1.0 / length));
+        if cosine >= crease_cosine - EPSILON {
+            normal = normal.add(neighbor);
+        }
+    }
+
+    normal.normalize()
 }
@@ -1,3 +1,6 @@
+use super::super::super::constants::EPSILON;
+use super::super::super::hit::TextureCoordinate;
+use super::super::super::onb::Onb;
 use super::super::super::ray::Ray;
 use super::super::super::vector::Vector;
 use super::super::aabb::AABB;
@@ -10,6 +13,80 @@ use std::f64::{MAX, MIN};
 pub struct ObjTriangle {
     pub vertexes: [Vector; 3],
     pub normals: [Vector; 3],
+    pub uvs: [TextureCoordinate; 3],
+    /// The direction of increasing u across the triangle, scaled by how
+    /// far apart u's values are.
+    pub tangent: Vector,
+    /// Likewise for v.
+    pub bitangent: Vector,
+}
+
+impl ObjTriangle {
+    pub fn new(
+        vertexes: [Vector; 3],
+        normals: [Vector; 3],
+        uvs: [TextureCoordinate; 3],
+    ) -> ObjTriangle {
+        let (tangent, bitangent) = tangents(vertexes, uvs);
+
+        ObjTriangle {
+            vertexes,
+            normals,
+            uvs,
+            tangent,
+            bitangent,
+        }
+    }
+
+    /// Blends the vertexes' normals by the barycentric weights of a point
+    /// on the triangle, so that light shades smoothly across meshes, or
+    /// returns None if they cancel out.
+    ///
+    /// https://en.wikipedia.org/wiki/Phong_shading
+    fn shading_normal(&self, weights: [f64; 3]) -> Option<Vector> {
+        let normal = self.normals[0]
+            .scale(weights[0])
+            .add(self.normals[1].scale(weights[1]))
+            .add(self.normals[2].scale(weights[2]));
+
+        if normal.length() < EPSILON {
+            return None;
+        }
+
+        Some(normal.normalize())
+    }
+}
+
+/// Solves for the derivatives of the position with respect to u and v,
+/// which map each of the triangle's edges to its change in texture
+/// coordinates. Triangles whose texture coordinates don't span an area
+/// get an arbitrary pair of directions along their surface instead.
+///
+/// https://www.pbr-book.org/3ed-2018/Shapes/Triangle_Meshes#TriangleIntersection
+fn tangents(vertexes: [Vector; 3], uvs: [TextureCoordinate; 3]) -> (Vector, Vector) {
+    let edge1 = vertexes[1].subtract(vertexes[0]);
+    let edge2 = vertexes[2].subtract(vertexes[0]);
+    let du1 = uvs[1].u - uvs[0].u;
+    let dv1 = uvs[1].v - uvs[0].v;
+    let du2 = uvs[2].u - uvs[0].u;
+    let dv2 = uvs[2].v - uvs[0].v;
+
+    let determinant = du1 * dv2 - du2 * dv1;
+    if determinant.abs() < EPSILON {
+        let onb = Onb::from_normal(edge1.cross_product(edge2).normalize());
+        return (onb.u, onb.v);
+    }
+
+    let tangent = edge1
+        .scale(dv2)
+        .subtract(edge2.scale(dv1))
+        .scale(1.0 / determinant);
+    let bitangent = edge2
+        .scale(du1)
+        .subtract(edge1.scale(du2))
+        .scale(1.0 / determinant);
+
+    (tangent, bitangent)
 }
 
 impl tree::TreeShape for ObjTriangle {
@@ -34,7 +111,15 @@ impl tree::TreeShape for ObjTriangle {
     }
 
     fn intersect(&self, ray: Ray) -> Option<Intersection> {
-        triangle::intersect_triangle(ray, *self)
+        triangle::intersect_triangle(ray, *self).map(|mut intersection| {
+            intersection.uv = TextureCoordinate::interpolate(self.uvs, intersection.barycentric);
+            if let Some(normal) = self.shading_normal(intersection.barycentric) {
+                intersection.shading_normal = normal;
+            }
+            intersection.tangent = self.tangent;
+            intersection.bitangent = self.bitangent;
+            intersection
+        })
     }
 }
 
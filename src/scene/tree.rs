@@ -116,12 +116,17 @@ pub struct TreeNodeIntersection {
 /// the order according to the ray's trajectory, trying to intersect each
 /// shape until its first hit.
 ///
+/// Intersections that `accept` turns down, given the shape's index, are
+/// passed through as if the shape weren't there, which lets shapes be cut
+/// out.
+///
 /// NOTE: It turns out to be more performant to pass in []TreeShape
 /// instead of a getter interface.
 pub fn intersect_tree_node(
     shapes: &Vec<Box<dyn TreeShape>>,
     tree_node: &Box<TreeNode>,
     ray: Ray,
+    accept: &dyn Fn(usize, &Intersection) -> bool,
 ) -> Option<TreeNodeIntersection> {
     if tree_node.left.is_some() && tree_node.right.is_some() {
         let left = tree_node.left.as_ref().unwrap();
@@ -132,32 +137,31 @@ pub fn intersect_tree_node(
 
         if left_intersection.ok() && right_intersection.ok() {
             if left_intersection.tmin() < right_intersection.tmin() {
-                let tree_node_intersection = intersect_tree_node(shapes, left, ray);
+                let tree_node_intersection = intersect_tree_node(shapes, left, ray, accept);
                 if tree_node_intersection.is_some() {
                     return tree_node_intersection;
                 }
-                return intersect_tree_node(shapes, right, ray);
+                return intersect_tree_node(shapes, right, ray, accept);
             }
-            let tree_node_intersection = intersect_tree_node(shapes, right, ray);
+            let tree_node_intersection = intersect_tree_node(shapes, right, ray, accept);
             if tree_node_intersection.is_some() {
                 return tree_node_intersection;
             }
-            return intersect_tree_node(shapes, left, ray);
+            return intersect_tree_node(shapes, left, ray, accept);
         }
 
         if left_intersection.ok() {
-            return intersect_tree_node(shapes, left, ray);
+            return intersect_tree_node(shapes, left, ray, accept);
         }
 
         if right_intersection.ok() {
-            return intersect_tree_node(shapes, right, ray);
+            return intersect_tree_node(shapes, right, ray, accept);
         }
 
         return None;
     }
 
-    let mut nearest_position = Vector::zeros();
-    let mut nearest_normal = Vector::zeros();
+    let mut nearest_intersection: Option<Intersection> = None;
     let mut nearest_distance = MAX;
     let mut nearest_shape_index: usize = 0;
 
@@ -181,22 +185,17 @@ pub fn intersect_tree_node(
             continue;
         }
 
-        nearest_position = shape_intersection.position;
-        nearest_normal = shape_intersection.normal;
+        if !accept(shape_index, &shape_intersection) {
+            continue;
+        }
+
         nearest_distance = shape_intersection.distance_from_origin;
+        nearest_intersection = Some(shape_intersection);
         nearest_shape_index = shape_index;
     }
 
-    if nearest_distance == MAX {
-        return None;
-    }
-
-    Option::from(TreeNodeIntersection {
-        hit: Hit {
-            from: ray,
-            position: nearest_position,
-            normal: nearest_normal,
-        },
+    nearest_intersection.map(|intersection| TreeNodeIntersection {
+        hit: intersection.hit(ray, nearest_shape_index),
         nearest_shape_index,
     })
 }
@@ -1,4 +1,5 @@
 use super::super::constants::EPSILON;
+use super::super::hit::TextureCoordinate;
 use super::super::ray::Ray;
 use super::super::vector::Vector;
 use super::intersection::Intersection;
@@ -10,7 +11,7 @@ pub trait Triangle {
 }
 
 /// IntersectTriangle determins if a ray passes through a triangle and at
-/// what distance from the origin if so.
+/// what distance from the origin and barycentric coordinates if so.
 ///
 /// https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/ray-triangle-intersection-geometric-solution
 /// http://geomalgorithms.com/a06-_intersect-2.html
@@ -19,12 +20,24 @@ pub fn intersect_triangle(ray: Ray, triangle: impl Triangle) -> Option<Intersect
         distance_from_origin: 0.0,
         position: Vector::zeros(),
         normal: Vector::zeros(),
+        shading_normal: Vector::zeros(),
+        tangent: Vector::zeros(),
+        bitangent: Vector::zeros(),
+        barycentric: [0.0; 3],
+        uv: TextureCoordinate::default(),
     };
 
     let v0v1 = triangle.vertex0().subtract(triangle.vertex1());
     let v0v2 = triangle.vertex0().subtract(triangle.vertex2());
 
-    intersection.normal = v0v1.cross_product(v0v2).normalize();
+    // Triangles without any area have no normal to speak of and can't be
+    // hit.
+    let cross_product = v0v1.cross_product(v0v2);
+    if cross_product.length() < EPSILON {
+        return None;
+    }
+
+    intersection.normal = cross_product.normalize();
 
     let cosine_of_ray_and_normal = ray.direction.dot_product(intersection.normal);
 
@@ -46,26 +59,37 @@ pub fn intersect_triangle(ray: Ray, triangle: impl Triangle) -> Option<Intersect
         .origin
         .add(ray.direction.scale(intersection.distance_from_origin));
 
+    // Each edge and the position form a smaller triangle, whose share of
+    // the whole triangle's area is the weight of the opposite vertex. The
+    // area is negative when the position is outside of the edge.
+    let area = cross_product.length();
+
     let triangle_edge = triangle.vertex1().subtract(triangle.vertex0());
     let point_edge = intersection.position.subtract(triangle.vertex0());
     let edges_cross_product = triangle_edge.cross_product(point_edge);
-    if intersection.normal.dot_product(edges_cross_product) < 0.0 {
+    let weight2 = intersection.normal.dot_product(edges_cross_product);
+    if weight2 < 0.0 {
         return None;
     }
 
     let triangle_edge = triangle.vertex2().subtract(triangle.vertex1());
     let point_edge = intersection.position.subtract(triangle.vertex1());
     let edges_cross_product = triangle_edge.cross_product(point_edge);
-    if intersection.normal.dot_product(edges_cross_product) < 0.0 {
+    let weight0 = intersection.normal.dot_product(edges_cross_product);
+    if weight0 < 0.0 {
         return None;
     }
 
     let triangle_edge = triangle.vertex0().subtract(triangle.vertex2());
     let point_edge = intersection.position.subtract(triangle.vertex2());
     let edges_cross_product = triangle_edge.cross_product(point_edge);
-    if intersection.normal.dot_product(edges_cross_product) < 0.0 {
+    let weight1 = intersection.normal.dot_product(edges_cross_product);
+    if weight1 < 0.0 {
         return None;
     }
 
+    intersection.barycentric = [weight0 / area, weight1 / area, weight2 / area];
+    intersection.shading_normal = intersection.normal;
+
     Option::from(intersection)
 }
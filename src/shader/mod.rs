@@ -0,0 +1,720 @@
+mod node;
+
+pub use node::{Input, Node, TextureSource};
+
+use super::color::{Color, BLACK, WHITE};
+use super::hit::Hit;
+use super::material::dispersion::Dispersion;
+use super::material::principled::Principled;
+use super::material::thin_film::ThinFilm;
+use super::material::{Bounce, Material};
+use super::random::Rng;
+use super::texture::{ColorSpace, GradientShape, Mapping, NoiseKind, Space, VoronoiOutput};
+use super::vector::Vector;
+use std::cell::Cell;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The principled parameters a graph can set, plus the opacity of cut
+/// outs. Scalar parameters take the luminance of their input. Surfaces
+/// only have a thin film if its thickness, in nanometers, is set, and
+/// only disperse light if their Abbe number is.
+pub const PARAMETERS: [&str; 17] = [
+    "base_color",
+    "metallic",
+    "roughness",
+    "anisotropic",
+    "specular",
+    "specular_tint",
+    "sheen",
+    "sheen_tint",
+    "clearcoat",
+    "clearcoat_roughness",
+    "transmission",
+    "index_of_refraction",
+    "abbe_number",
+    "emission",
+    "thin_film_thickness",
+    "thin_film_index_of_refraction",
+    "opacity",
+];
+
+/// A material whose principled parameters are computed at each hit by a
+/// graph of nodes, so that looks can be authored in a text file rather
+/// than in code.
+///
+/// Each line of the file either names a node, which may read from those
+/// named above it, or connects an input to one of the `PARAMETERS`:
+///
+/// ```text
+/// # Rusted paint.
+/// rust = noise fbm space=world scale=4,4,4
+/// paint = mix 0.1,0.3,0.6 0.35,0.15,0.05 rust
+/// rim = layer_weight 0.3
+/// output base_color paint
+/// output roughness rim
+/// output metallic 0
+/// ```
+///
+/// Inputs are either the name of a node or a constant color, written as a
+/// single number for grays or as comma separated channels. The nodes are:
+///
+/// - `image <path> [linear]`
+/// - `checker [options]`
+/// - `noise perlin|fbm|turbulence [options]`
+/// - `voronoi distance|edge|cell [options]`
+/// - `gradient linear <axis>|radial [options] [<position>:<color>...]`
+/// - `mix <a> <b> <factor>`
+/// - `multiply <a> <b>`
+/// - `remap <input> <from min> <from max> <to min> <to max>`
+/// - `fresnel <index of refraction>`
+/// - `layer_weight <blend>`
+/// - `ramp <input> <position>:<color>...`
+///
+/// where the options of procedural textures are `space=uv|world`,
+/// `scale=x,y,z` and `offset=x,y,z`. Gradients without colors go from
+/// black to white. Paths with spaces or # in them go in
+/// double quotes, inside of which quotes and backslashes are escaped with
+/// a backslash.
+///
+/// https://docs.blender.org/manual/en/latest/render/shader_nodes/index.html
+pub struct ShaderGraph {
+    /// Tells the graph apart from others in the cache of evaluations.
+    id: u64,
+    names: Vec<String>,
+    /// Nodes only read from those before them, which is the order they're
+    /// evaluated in.
+    nodes: Vec<Node>,
+    outputs: Vec<(String, Input)>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl ShaderGraph {
+    /// Builds a graph in code, with a name for each node. Nodes may only
+    /// read from those before them, and outputs may only connect to
+    /// `PARAMETERS`.
+    pub fn new(
+        names: Vec<String>,
+        nodes: Vec<Node>,
+        outputs: Vec<(String, Input)>,
+    ) -> Result<ShaderGraph, String> {
+        if names.len() != nodes.len() {
+            return Err("every node needs a name".to_string());
+        }
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(format!("{} is already a node", name));
+            }
+        }
+
+        let reads_before = |input: &Input, count: usize| match input {
+            Input::Node(index) => *index < count,
+            Input::Constant(_) => true,
+        };
+        for (i, node) in nodes.iter().enumerate() {
+            if !node.inputs().iter().all(|input| reads_before(input, i)) {
+                return Err(format!("{} reads from a node after it", names[i]));
+            }
+        }
+        for (parameter, input) in outputs.iter() {
+            if !PARAMETERS.contains(&parameter.as_str()) {
+                return Err(format!("{} is not a parameter", parameter));
+            }
+            if !reads_before(input, nodes.len()) {
+                return Err(format!("{} reads from a node that isn't there", parameter));
+            }
+        }
+
+        Ok(ShaderGraph {
+            id: next_id(),
+            names,
+            nodes,
+            outputs,
+        })
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn outputs(&self) -> &[(String, Input)] {
+        &self.outputs
+    }
+
+    /// Loads a graph from a file, looking up images next to it before the
+    /// search paths.
+    pub fn open(path: &Path, search_paths: &[PathBuf]) -> io::Result<ShaderGraph> {
+        let input = fs::read_to_string(path)?;
+
+        let mut search_paths = search_paths.to_vec();
+        if let Some(directory) = path.parent() {
+            search_paths.insert(0, directory.to_path_buf());
+        }
+
+        ShaderGraph::parse(&input, &search_paths).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), error),
+            )
+        })
+    }
+
+    pub fn parse(input: &str, search_paths: &[PathBuf]) -> Result<ShaderGraph, ParseError> {
+        let mut graph = ShaderGraph {
+            id: next_id(),
+            names: vec![],
+            nodes: vec![],
+            outputs: vec![],
+        };
+
+        for (i, line) in input.lines().enumerate() {
+            let error = |message: String| ParseError {
+                line: i + 1,
+                message,
+            };
+
+            let words = split_words(line).map_err(error)?;
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            match words.as_slice() {
+                [] => continue,
+                ["output", parameter, input] => {
+                    if !PARAMETERS.contains(parameter) {
+                        return Err(error(format!("{} is not a parameter", parameter)));
+                    }
+                    let input = graph.parse_input(input).map_err(error)?;
+                    graph.outputs.push((parameter.to_string(), input));
+                }
+                [name, "=", kind, arguments @ ..] => {
+                    if graph.names.iter().any(|a| a == name) {
+                        return Err(error(format!("{} is already a node", name)));
+                    }
+                    let node = graph
+                        .parse_node(kind, arguments, search_paths)
+                        .map_err(error)?;
+                    graph.names.push(name.to_string());
+                    graph.nodes.push(node);
+                }
+                _ => return Err(error(format!("{} is not a node or output", line.trim()))),
+            }
+        }
+
+        Ok(graph)
+    }
+
+    fn parse_node(
+        &self,
+        kind: &str,
+        arguments: &[&str],
+        search_paths: &[PathBuf],
+    ) -> Result<Node, String> {
+        let input = |i: usize| match arguments.get(i) {
+            Some(a) => self.parse_input(a),
+            None => Err(format!("{} is missing an input", kind)),
+        };
+        let texture = |source: TextureSource| {
+            let texture = source.texture(search_paths)?;
+            Ok(Node::Texture(source, texture))
+        };
+
+        match kind {
+            "image" => {
+                let color_space = match arguments.get(1) {
+                    Some(&"linear") => ColorSpace::Linear,
+                    _ => ColorSpace::Srgb,
+                };
+                match arguments.first() {
+                    Some(path) => texture(TextureSource::Image(path.to_string(), color_space)),
+                    None => Err("image is missing a path".to_string()),
+                }
+            }
+            "checker" => texture(TextureSource::Checker(parse_mapping(arguments)?)),
+            "noise" => {
+                let noise_kind = match arguments.first() {
+                    Some(&"perlin") => NoiseKind::Perlin,
+                    Some(&"fbm") => NoiseKind::Fbm,
+                    Some(&"turbulence") => NoiseKind::Turbulence,
+                    _ => return Err("noise is missing perlin, fbm or turbulence".to_string()),
+                };
+                texture(TextureSource::Noise(
+                    noise_kind,
+                    parse_mapping(&arguments[1..])?,
+                ))
+            }
+            "voronoi" => {
+                let output = match arguments.first() {
+                    Some(&"distance") => VoronoiOutput::Distance,
+                    Some(&"edge") => VoronoiOutput::Edge,
+                    Some(&"cell") => VoronoiOutput::Cell,
+                    _ => return Err("voronoi is missing distance, edge or cell".to_string()),
+                };
+                texture(TextureSource::Voronoi(
+                    output,
+                    parse_mapping(&arguments[1..])?,
+                ))
+            }
+            "gradient" => {
+                let (shape, rest) = match arguments.first() {
+                    Some(&"radial") => (GradientShape::Radial, &arguments[1..]),
+                    Some(&"linear") => match arguments.get(1) {
+                        Some(axis) => (GradientShape::Linear(parse_vector(axis)?), &arguments[2..]),
+                        None => return Err("linear gradient is missing an axis".to_string()),
+                    },
+                    _ => return Err("gradient is missing linear or radial".to_string()),
+                };
+                let (options, stops): (Vec<&str>, Vec<&str>) =
+                    rest.iter().partition(|argument| argument.contains('='));
+                let stops = if stops.is_empty() {
+                    vec![(0.0, BLACK), (1.0, WHITE)]
+                } else {
+                    parse_stops(&stops)?
+                };
+                texture(TextureSource::Gradient(
+                    shape,
+                    parse_mapping(&options)?,
+                    stops,
+                ))
+            }
+            "mix" => Ok(Node::Mix(input(0)?, input(1)?, input(2)?)),
+            "multiply" => Ok(Node::Multiply(input(0)?, input(1)?)),
+            "remap" => {
+                let mut range = [0.0; 4];
+                for (j, value) in range.iter_mut().enumerate() {
+                    let argument = arguments
+                        .get(j + 1)
+                        .ok_or_else(|| "remap is missing a range".to_string())?;
+                    *value = parse_number(argument)?;
+                }
+                Ok(Node::Remap(input(0)?, range))
+            }
+            "fresnel" => Ok(Node::Fresnel(input(0)?)),
+            "layer_weight" => Ok(Node::LayerWeight(input(0)?)),
+            "ramp" => {
+                let stops = parse_stops(arguments.get(1..).unwrap_or(&[]))?;
+                if stops.is_empty() {
+                    return Err("ramp has no colors".to_string());
+                }
+                Ok(Node::ColorRamp(input(0)?, stops))
+            }
+            _ => Err(format!("{} is not a kind of node", kind)),
+        }
+    }
+
+    /// Reads the name of an earlier node or a constant color.
+    fn parse_input(&self, argument: &str) -> Result<Input, String> {
+        if let Some(index) = self.names.iter().position(|name| name == argument) {
+            return Ok(Input::Node(index));
+        }
+
+        parse_color(argument)
+            .map(Input::Constant)
+            .map_err(|_| format!("{} is not a node above or a color", argument))
+    }
+
+    /// The value each output sets its parameter to at the hit, evaluating
+    /// every node once.
+    pub fn outputs_at<'a>(&'a self, hit: &Hit) -> impl Iterator<Item = (&'a str, Color)> + 'a {
+        let mut colors: Vec<Color> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let color = node.evaluate(hit, &colors);
+            colors.push(color);
+        }
+
+        self.outputs
+            .iter()
+            .map(move |(parameter, input)| (parameter.as_str(), input.color(&colors)))
+    }
+
+    /// The parameters at the hit, which are only evaluated again once a
+    /// different hit or graph is evaluated on the same thread.
+    fn evaluation(&self, hit: &Hit) -> Evaluation {
+        let key = hit_key(self.id, hit);
+
+        LAST_EVALUATION.with(|last| {
+            if let Some((last_key, evaluation)) = last.get() {
+                if last_key == key {
+                    return evaluation;
+                }
+            }
+
+            let evaluation = self.evaluate_parameters(hit);
+            last.set(Some((key, evaluation)));
+            evaluation
+        })
+    }
+
+    fn principled_at(&self, hit: &Hit) -> Principled {
+        self.evaluation(hit).principled
+    }
+
+    fn evaluate_parameters(&self, hit: &Hit) -> Evaluation {
+        let mut principled = Principled::new();
+        let mut opacity = 1.0;
+        let mut film = ThinFilm::new(0.0, 1.33);
+        let mut abbe_number = None;
+
+        for (parameter, color) in self.outputs_at(hit) {
+            let value = color.luminance();
+            match parameter {
+                "base_color" => principled.base_color = color,
+                "metallic" => principled.metallic = value,
+                "roughness" => principled.roughness = value,
+                "anisotropic" => principled.anisotropic = value,
+                "specular" => principled.specular = value,
+                "specular_tint" => principled.specular_tint = value,
+                "sheen" => principled.sheen = value,
+                "sheen_tint" => principled.sheen_tint = value,
+                "clearcoat" => principled.clearcoat = value,
+                "clearcoat_roughness" => principled.clearcoat_roughness = value,
+                "transmission" => principled.transmission = value,
+                "index_of_refraction" => principled.index_of_refraction = value,
+                "abbe_number" => abbe_number = Some(value),
+                "emission" => principled.emission = color,
+                "thin_film_thickness" => {
+                    film.thickness = value;
+                    principled.thin_film = Some(film);
+                }
+                "thin_film_index_of_refraction" => film.index_of_refraction = value,
+                "opacity" => opacity = value,
+                _ => continue,
+            }
+        }
+
+        if let Some(thin_film) = principled.thin_film.as_mut() {
+            *thin_film = film;
+        }
+        principled.dispersion = abbe_number.map(|abbe_number| {
+            Dispersion::from_abbe_number(principled.index_of_refraction, abbe_number)
+        });
+
+        Evaluation {
+            principled,
+            opacity,
+        }
+    }
+}
+
+/// A graph's parameters at a hit.
+#[derive(Clone, Copy)]
+struct Evaluation {
+    principled: Principled,
+    opacity: f64,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The parameters last evaluated on each thread, along with the graph
+    /// and hit they were evaluated for. Paths ask the surface at each of
+    /// their vertexes for its emittance, samples and pdfs at the same hit,
+    /// which then only evaluates the graph once.
+    static LAST_EVALUATION: Cell<Option<([u64; 13], Evaluation)>> = const { Cell::new(None) };
+}
+
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Identifies the graph and everything about the hit its nodes may look
+// up, which is where and from which direction the surface is hit.
+fn hit_key(id: u64, hit: &Hit) -> [u64; 13] {
+    [
+        id,
+        hit.shape_index as u64,
+        hit.position.x.to_bits(),
+        hit.position.y.to_bits(),
+        hit.position.z.to_bits(),
+        hit.from.direction.x.to_bits(),
+        hit.from.direction.y.to_bits(),
+        hit.from.direction.z.to_bits(),
+        hit.shading_normal.x.to_bits(),
+        hit.shading_normal.y.to_bits(),
+        hit.shading_normal.z.to_bits(),
+        hit.uv.u.to_bits(),
+        hit.uv.v.to_bits(),
+    ]
+}
+
+impl Material for ShaderGraph {
+    fn emittance(&self, hit: &Hit) -> Color {
+        let emission = self.outputs.iter().any(|(name, _)| name == "emission");
+        if !emission {
+            return BLACK;
+        }
+
+        self.principled_at(hit).emittance(hit)
+    }
+
+    fn evaluate(&self, hit: &Hit, direction: Vector) -> Color {
+        self.principled_at(hit).evaluate(hit, direction)
+    }
+
+    fn pdf(&self, hit: &Hit, direction: Vector) -> f64 {
+        self.principled_at(hit).pdf(hit, direction)
+    }
+
+    fn sample(&self, random: &mut Box<dyn Rng>, hit: &Hit) -> Option<Bounce> {
+        self.principled_at(hit).sample(random, hit)
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        let opacity = self.outputs.iter().any(|(name, _)| name == "opacity");
+        if !opacity {
+            return 1.0;
+        }
+
+        self.evaluation(hit).opacity
+    }
+
+    fn disperses(&self, hit: &Hit) -> bool {
+        let dispersion = self.outputs.iter().any(|(name, _)| name == "abbe_number");
+        dispersion && self.principled_at(hit).disperses(hit)
+    }
+
+    /// Graphs that set the transmission keep their surfaces, even if it is
+    /// only transmissive in places.
+    fn interior_keeps_surface(&self) -> bool {
+        self.outputs.iter().any(|(name, _)| name == "transmission")
+    }
+}
+
+/// Writes the graph back out in the form it is read in.
+impl fmt::Display for ShaderGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, node) in self.names.iter().zip(self.nodes.iter()) {
+            let node = node::Display {
+                node,
+                names: &self.names,
+            };
+            writeln!(f, "{} = {}", name, node)?;
+        }
+
+        for (parameter, input) in self.outputs.iter() {
+            let input = node::InputDisplay {
+                input: *input,
+                names: &self.names,
+            };
+            writeln!(f, "output {} {}", parameter, input)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits the line into words at whitespace, up to any comment. Words in
+/// double quotes may hold whitespace and #, as well as quotes and
+/// backslashes escaped by a backslash.
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut characters = line.chars();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '#' => break,
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match characters.next() {
+                        Some('"') => break,
+                        Some('\\') => match characters.next() {
+                            Some(escaped) => word.push(escaped),
+                            None => return Err("a quote is never closed".to_string()),
+                        },
+                        Some(a) => word.push(a),
+                        None => return Err("a quote is never closed".to_string()),
+                    }
+                }
+            }
+            a if a.is_whitespace() => words.extend(word.take()),
+            a => word.get_or_insert_with(String::new).push(a),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// Reads a finite number. Infinities and NaN are rejected, as nothing in
+/// a graph means anything with them.
+fn parse_number(argument: &str) -> Result<f64, String> {
+    match argument.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(format!("{} is not a number", argument)),
+    }
+}
+
+/// Reads a single number as a shade of gray or three separated by commas
+/// as an RGB color.
+fn parse_color(argument: &str) -> Result<Color, String> {
+    let values = parse_numbers(argument)?;
+
+    match values.len() {
+        1 => Ok(Color::new(values[0], values[0], values[0])),
+        3 => Ok(Color::new(values[0], values[1], values[2])),
+        _ => Err(format!("{} is not an RGB color", argument)),
+    }
+}
+
+/// Reads a single number as the same value along every axis or three
+/// separated by commas.
+fn parse_vector(argument: &str) -> Result<Vector, String> {
+    let values = parse_numbers(argument)?;
+
+    match values.len() {
+        1 => Ok(Vector::new(values[0], values[0], values[0])),
+        3 => Ok(Vector::new(values[0], values[1], values[2])),
+        _ => Err(format!("{} is not a vector", argument)),
+    }
+}
+
+fn parse_numbers(argument: &str) -> Result<Vec<f64>, String> {
+    argument.split(',').map(parse_number).collect()
+}
+
+/// Reads colors placed at positions, as `position:color`, sorting them
+/// by position.
+fn parse_stops(arguments: &[&str]) -> Result<Vec<(f64, Color)>, String> {
+    let mut stops = vec![];
+    for argument in arguments.iter() {
+        let (position, color) = match argument.find(':') {
+            Some(a) => (&argument[..a], &argument[a + 1..]),
+            None => return Err(format!("{} is not a position:color", argument)),
+        };
+        stops.push((parse_number(position)?, parse_color(color)?));
+    }
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    Ok(stops)
+}
+
+fn parse_mapping(arguments: &[&str]) -> Result<Mapping, String> {
+    let mut mapping = Mapping::new(Space::Uv);
+
+    for argument in arguments.iter() {
+        let (key, value) = match argument.find('=') {
+            Some(a) => (&argument[..a], &argument[a + 1..]),
+            None => return Err(format!("{} is not an option", argument)),
+        };
+
+        match key {
+            "space" if value == "uv" => mapping.space = Space::Uv,
+            "space" if value == "world" => mapping.space = Space::World,
+            "scale" => mapping.scale = parse_vector(value)?,
+            "offset" => mapping.offset = parse_vector(value)?,
+            _ => return Err(format!("{} is not an option", argument)),
+        }
+    }
+
+    Ok(mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUSTED_PAINT: &str = "
+        # Rusted paint.
+        rust = noise fbm space=world scale=4
+        paint = mix 0.1,0.3,0.6 0.35,0.15,0.05 rust
+        rim = layer_weight 0.3
+        shade = ramp rim 0:0.2 1:0.8
+        output base_color paint
+        output roughness shade
+        output metallic 0
+    ";
+
+    #[test]
+    fn round_trips_through_text() {
+        let graph = ShaderGraph::parse(RUSTED_PAINT, &[]).unwrap();
+        assert_eq!(graph.names, vec!["rust", "paint", "rim", "shade"]);
+        assert_eq!(graph.outputs.len(), 3);
+
+        let written = graph.to_string();
+        let reread = ShaderGraph::parse(&written, &[]).unwrap();
+        assert_eq!(reread.to_string(), written);
+
+        let gradients = "
+            sky = gradient linear 0,1,0 space=world 0:1 1:0.2,0.4,0.9
+            glow = gradient radial scale=2
+            output base_color sky
+            output emission glow
+        ";
+        let written = ShaderGraph::parse(gradients, &[]).unwrap().to_string();
+        let reread = ShaderGraph::parse(&written, &[]).unwrap();
+        assert_eq!(reread.to_string(), written);
+
+        assert!(ShaderGraph::parse("a = mix b 0 0.5", &[]).is_err());
+        assert!(ShaderGraph::parse("a = gradient linear", &[]).is_err());
+        assert!(ShaderGraph::parse("a = gradient radial 0.5", &[]).is_err());
+        assert!(ShaderGraph::parse("output shininess 1", &[]).is_err());
+        assert!(ShaderGraph::parse("r = ramp 0.5 nan:1 0:0", &[]).is_err());
+        assert!(ShaderGraph::parse("output roughness inf", &[]).is_err());
+        assert!(ShaderGraph::parse("a = image \"b.png", &[]).is_err());
+    }
+
+    /// Graphs built in code shouldn't be able to read from nodes that
+    /// aren't evaluated yet, which would panic once rendered.
+    #[test]
+    fn rejects_inputs_out_of_order() {
+        let names = || vec!["a".to_string(), "b".to_string()];
+        let gray = Input::Constant(Color::new(0.5, 0.5, 0.5));
+        let nodes = |input: Input| vec![Node::Fresnel(gray), Node::LayerWeight(input)];
+        let output = |input: Input| vec![("roughness".to_string(), input)];
+
+        assert!(ShaderGraph::new(names(), nodes(Input::Node(0)), output(Input::Node(1))).is_ok());
+        assert!(ShaderGraph::new(names(), nodes(Input::Node(1)), output(gray)).is_err());
+        assert!(ShaderGraph::new(names(), nodes(gray), output(Input::Node(2))).is_err());
+        assert!(ShaderGraph::new(names(), nodes(gray), vec![("shine".to_string(), gray)]).is_err());
+        assert!(ShaderGraph::new(vec!["a".to_string()], nodes(gray), output(gray)).is_err());
+    }
+
+    /// Images whose paths have spaces in them should be quoted on the way
+    /// out and read back the same.
+    #[test]
+    fn round_trips_quoted_paths() {
+        let directory = std::env::temp_dir().join("shader graph test");
+        fs::create_dir_all(&directory).unwrap();
+        let name = "rusty paint #2.png";
+        image::RgbaImage::new(1, 1)
+            .save(directory.join(name))
+            .unwrap();
+
+        let search_paths = [directory];
+        let input = "a = image \"rusty paint #2.png\" linear # A comment.";
+        let graph = ShaderGraph::parse(input, &search_paths).unwrap();
+        match &graph.nodes[0] {
+            Node::Texture(TextureSource::Image(path, ColorSpace::Linear), _) => {
+                assert_eq!(path, name)
+            }
+            _ => panic!("expected an image"),
+        }
+
+        let written = graph.to_string();
+        let reread = ShaderGraph::parse(&written, &search_paths).unwrap();
+        assert_eq!(reread.to_string(), written);
+
+        let words = split_words(r#"a "b \"c\" \\d" "" e#f"#).unwrap();
+        assert_eq!(words, vec!["a", r#"b "c" \d"#, "", "e"]);
+    }
+}
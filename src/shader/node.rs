@@ -0,0 +1,291 @@
+use super::super::color::{Color, BLACK, WHITE};
+use super::super::hit::Hit;
+use super::super::material::dielectric;
+use super::super::texture::{
+    find_file, ramp, Checker, ColorSpace, ConstantTexture, Gradient, GradientShape, ImageTexture,
+    Mapping, Noise, NoiseKind, Space, Texture, Voronoi, VoronoiOutput,
+};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// What feeds a node's input or a material's parameter: either one of
+/// the graph's nodes, by its index, or a fixed color.
+#[derive(Clone, Copy)]
+pub enum Input {
+    Node(usize),
+    Constant(Color),
+}
+
+impl Input {
+    /// The input's color, given those of the graph's nodes evaluated so
+    /// far.
+    pub fn color(&self, colors: &[Color]) -> Color {
+        match self {
+            Input::Node(index) => colors[*index],
+            Input::Constant(color) => *color,
+        }
+    }
+}
+
+/// A texture, along with what it was made from so that it can be written
+/// back out.
+pub enum TextureSource {
+    Image(String, ColorSpace),
+    Checker(Mapping),
+    Noise(NoiseKind, Mapping),
+    Voronoi(VoronoiOutput, Mapping),
+    Gradient(GradientShape, Mapping, Vec<(f64, Color)>),
+}
+
+/// One step in computing a material's parameters at a hit.
+pub enum Node {
+    /// Looks up a texture, which procedural ones do in black and white.
+    Texture(TextureSource, Box<dyn Texture>),
+    /// Blends from the first input to the second by the factor.
+    Mix(Input, Input, Input),
+    Multiply(Input, Input),
+    /// Linearly maps the input from one range of values to another.
+    Remap(Input, [f64; 4]),
+    /// The share of light reflected off of a dielectric of the index of
+    /// refraction at the angle the hit's ray arrives at.
+    Fresnel(Input),
+    /// How much the surface faces away from the hit's ray, from 0 head on
+    /// to 1 at grazing angles. Blends below 0.5 widen the falloff and
+    /// ones above narrow it.
+    LayerWeight(Input),
+    /// Maps the input's value onto colors placed at positions from 0 to
+    /// 1, blending between them.
+    ColorRamp(Input, Vec<(f64, Color)>),
+}
+
+impl Node {
+    /// The inputs the node reads from.
+    pub fn inputs(&self) -> Vec<Input> {
+        match self {
+            Node::Texture(_, _) => vec![],
+            Node::Mix(a, b, factor) => vec![*a, *b, *factor],
+            Node::Multiply(a, b) => vec![*a, *b],
+            Node::Remap(input, _)
+            | Node::Fresnel(input)
+            | Node::LayerWeight(input)
+            | Node::ColorRamp(input, _) => vec![*input],
+        }
+    }
+
+    /// Computes the node's color, reading its inputs from the colors of
+    /// the nodes before it.
+    pub fn evaluate(&self, hit: &Hit, colors: &[Color]) -> Color {
+        match self {
+            Node::Texture(_, texture) => texture.color(hit),
+            Node::Mix(a, b, factor) => {
+                let factor = factor.color(colors).luminance();
+                a.color(colors).mix(b.color(colors), factor)
+            }
+            Node::Multiply(a, b) => a.color(colors).multiply(b.color(colors)),
+            Node::Remap(input, [from_min, from_max, to_min, to_max]) => {
+                let remap = |value: f64| {
+                    if from_max == from_min {
+                        return *to_min;
+                    }
+                    to_min + (value - from_min) / (from_max - from_min) * (to_max - to_min)
+                };
+                let color = input.color(colors);
+                Color::new(remap(color.r), remap(color.g), remap(color.b))
+            }
+            Node::Fresnel(input) => {
+                let cosine = facing_cosine(hit);
+                let index_of_refraction = input.color(colors).luminance().max(1.0);
+                WHITE.scale(dielectric::fresnel(cosine, index_of_refraction))
+            }
+            Node::LayerWeight(input) => {
+                let blend = input.color(colors).luminance().clamp(0.0, 1.0 - 1e-5);
+                let exponent = if blend < 0.5 {
+                    2.0 * blend
+                } else {
+                    0.5 / (1.0 - blend)
+                };
+                WHITE.scale(1.0 - facing_cosine(hit).powf(exponent))
+            }
+            Node::ColorRamp(input, stops) => ramp(stops, input.color(colors).luminance()),
+        }
+    }
+}
+
+impl TextureSource {
+    /// Builds the texture, looking up the relative paths of images in the
+    /// directories in order.
+    pub fn texture(&self, search_paths: &[PathBuf]) -> Result<Box<dyn Texture>, String> {
+        let black = || Box::new(ConstantTexture::new(BLACK));
+        let white = || Box::new(ConstantTexture::new(WHITE));
+
+        Ok(match self {
+            TextureSource::Image(name, color_space) => {
+                let path = find_file(Path::new(name), search_paths);
+                let texture = ImageTexture::open(&path, *color_space)
+                    .map_err(|error| format!("{}: {}", path.display(), error))?;
+                Box::new(texture)
+            }
+            TextureSource::Checker(mapping) => Box::new(Checker::new(*mapping, white(), black())),
+            TextureSource::Noise(kind, mapping) => {
+                Box::new(Noise::new(*mapping, *kind, black(), white()))
+            }
+            TextureSource::Voronoi(output, mapping) => Box::new(Voronoi::new(*mapping, *output)),
+            TextureSource::Gradient(shape, mapping, stops) => {
+                Box::new(Gradient::new(*mapping, *shape, stops.clone()))
+            }
+        })
+    }
+}
+
+/// The cosine between the hit's ray and the shading normal.
+fn facing_cosine(hit: &Hit) -> f64 {
+    hit.facing_shading_normal()
+        .dot_product(hit.from.direction.scale(-1.0))
+        .clamp(0.0, 1.0)
+}
+
+/// Writes the node in the same form it is read in, given the names of
+/// the graph's nodes.
+pub struct Display<'a> {
+    pub node: &'a Node,
+    pub names: &'a [String],
+}
+
+impl<'a> fmt::Display for Display<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let input = |input: &Input| InputDisplay {
+            input: *input,
+            names: self.names,
+        };
+
+        match self.node {
+            Node::Texture(TextureSource::Image(path, color_space), _) => {
+                write!(f, "image {}", quoted(path))?;
+                if *color_space == ColorSpace::Linear {
+                    write!(f, " linear")?;
+                }
+                Ok(())
+            }
+            Node::Texture(TextureSource::Checker(mapping), _) => {
+                write!(f, "checker {}", MappingDisplay(mapping))
+            }
+            Node::Texture(TextureSource::Noise(kind, mapping), _) => {
+                let kind = match kind {
+                    NoiseKind::Perlin => "perlin",
+                    NoiseKind::Fbm => "fbm",
+                    NoiseKind::Turbulence => "turbulence",
+                };
+                write!(f, "noise {} {}", kind, MappingDisplay(mapping))
+            }
+            Node::Texture(TextureSource::Voronoi(output, mapping), _) => {
+                let output = match output {
+                    VoronoiOutput::Distance => "distance",
+                    VoronoiOutput::Edge => "edge",
+                    VoronoiOutput::Cell => "cell",
+                };
+                write!(f, "voronoi {} {}", output, MappingDisplay(mapping))
+            }
+            Node::Texture(TextureSource::Gradient(shape, mapping, stops), _) => {
+                match shape {
+                    GradientShape::Linear(axis) => {
+                        write!(f, "gradient linear {},{},{}", axis.x, axis.y, axis.z)?
+                    }
+                    GradientShape::Radial => write!(f, "gradient radial")?,
+                }
+                write!(f, " {}", MappingDisplay(mapping))?;
+                write_stops(f, stops)
+            }
+            Node::Mix(a, b, factor) => {
+                write!(f, "mix {} {} {}", input(a), input(b), input(factor))
+            }
+            Node::Multiply(a, b) => write!(f, "multiply {} {}", input(a), input(b)),
+            Node::Remap(value, range) => write!(
+                f,
+                "remap {} {} {} {} {}",
+                input(value),
+                range[0],
+                range[1],
+                range[2],
+                range[3]
+            ),
+            Node::Fresnel(index_of_refraction) => {
+                write!(f, "fresnel {}", input(index_of_refraction))
+            }
+            Node::LayerWeight(blend) => write!(f, "layer_weight {}", input(blend)),
+            Node::ColorRamp(value, stops) => {
+                write!(f, "ramp {}", input(value))?;
+                write_stops(f, stops)
+            }
+        }
+    }
+}
+
+// Writes the stops of a ramp or gradient, each after a space.
+fn write_stops(f: &mut fmt::Formatter, stops: &[(f64, Color)]) -> fmt::Result {
+    for (position, color) in stops.iter() {
+        write!(f, " {}:{}", position, ColorDisplay(*color))?;
+    }
+    Ok(())
+}
+
+pub struct InputDisplay<'a> {
+    pub input: Input,
+    pub names: &'a [String],
+}
+
+impl<'a> fmt::Display for InputDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.input {
+            Input::Node(index) => write!(f, "{}", self.names[index]),
+            Input::Constant(color) => write!(f, "{}", ColorDisplay(color)),
+        }
+    }
+}
+
+/// Writes a gray as a single number and any other color as its channels
+/// separated by commas.
+pub struct ColorDisplay(pub Color);
+
+impl fmt::Display for ColorDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Color { r, g, b } = self.0;
+        if r == g && g == b {
+            return write!(f, "{}", r);
+        }
+
+        write!(f, "{},{},{}", r, g, b)
+    }
+}
+
+struct MappingDisplay<'a>(&'a Mapping);
+
+impl<'a> fmt::Display for MappingDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let space = match self.0.space {
+            Space::Uv => "uv",
+            Space::World => "world",
+        };
+        let scale = self.0.scale;
+        let offset = self.0.offset;
+        write!(
+            f,
+            "space={} scale={},{},{} offset={},{},{}",
+            space, scale.x, scale.y, scale.z, offset.x, offset.y, offset.z
+        )
+    }
+}
+
+/// The word as it is read back in, which is in double quotes if it holds
+/// anything that would otherwise split it up or be taken for a comment.
+fn quoted(word: &str) -> String {
+    let plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|a| a.is_whitespace() || a == '"' || a == '#' || a == '\\');
+    if plain {
+        return word.to_string();
+    }
+
+    let escaped = word.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
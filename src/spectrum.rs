@@ -0,0 +1,198 @@
+use super::color::Color;
+
+/// The range of wavelengths of visible light, in nanometers.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 720.0;
+
+/// How many wavelengths `to_rgb` samples a spectrum at.
+const SAMPLE_COUNT: usize = 35;
+
+/// Where the spectra that colors are upsampled to switch from blue to
+/// green and from green to red, and how gradually they do so, in
+/// nanometers.
+const BLUE_TO_GREEN: f64 = 490.0;
+const GREEN_TO_RED: f64 = 590.0;
+const TRANSITION_WIDTH: f64 = 8.0;
+
+/// The wavelengths, in nanometers, that a path carries through a spectral
+/// render in place of red, green and blue. Colors along such a path hold
+/// their values at these wavelengths rather than in those channels.
+///
+/// The first is the hero wavelength, which is picked at random, and the
+/// others are spaced evenly after it across the visible spectrum, wrapping
+/// around at its end. Every wavelength is thus equally likely and most of
+/// the path's work is shared between all three of them.
+///
+/// https://cgg.mff.cuni.cz/~wilkie/Website/EGSR_14_files/WNDWH14HWSS.pdf
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths(pub [f64; 3]);
+
+impl Wavelengths {
+    /// Picks the wavelengths from a number between 0 and 1.
+    pub fn sample(u: f64) -> Wavelengths {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let at = |offset: f64| MIN_WAVELENGTH + ((u + offset) % 1.0) * range;
+        Wavelengths([at(0.0), at(1.0 / 3.0), at(2.0 / 3.0)])
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.0[0]
+    }
+
+    /// The values at the wavelengths of a function of wavelength.
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Color {
+        Color::new(f(self.0[0]), f(self.0[1]), f(self.0[2]))
+    }
+
+    /// The values at the wavelengths of a smooth spectrum that looks like
+    /// the color. The spectrum blends the color's channels across the
+    /// parts of the spectrum they cover most, so white is one everywhere
+    /// and the spectra of colors add and scale just as the colors do.
+    pub fn upsample(&self, color: Color) -> Color {
+        self.map(|wavelength| {
+            let green_or_red = smoothstep(wavelength, BLUE_TO_GREEN);
+            let red = smoothstep(wavelength, GREEN_TO_RED);
+            color.b * (1.0 - green_or_red) + color.g * (green_or_red - red) + color.r * red
+        })
+    }
+
+    /// Drops every wavelength but the hero, as after a surface that sends
+    /// each wavelength in a different direction. The hero stands in for
+    /// the others from then on, so it's weighed by all three.
+    pub fn keep_hero(&self, color: Color) -> Color {
+        Color::new(3.0 * color.r, 0.0, 0.0)
+    }
+
+    /// Converts light carried at the wavelengths into a color. Light with
+    /// the same value at every wavelength is that gray.
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        let values = [radiance.r, radiance.g, radiance.b];
+        let mut xyz = [0.0; 3];
+        for (value, &wavelength) in values.iter().zip(self.0.iter()) {
+            for (j, matching) in cie_xyz(wavelength).iter().enumerate() {
+                xyz[j] += value * matching;
+            }
+        }
+
+        let color = xyz_to_rgb(xyz);
+        let white = white();
+        let scale = SAMPLE_COUNT as f64 / 3.0;
+        Color::new(
+            color.r / white.r * scale,
+            color.g / white.g * scale,
+            color.b / white.b * scale,
+        )
+    }
+}
+
+/// The color as a path sees it, which is its values at the path's
+/// wavelengths in spectral renders and the color itself otherwise.
+pub fn upsample(color: Color, wavelengths: Option<Wavelengths>) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample(color),
+        None => color,
+    }
+}
+
+/// The CIE 1931 color matching functions at the wavelength, which are how
+/// strongly light of that wavelength stimulates each of the X, Y and Z
+/// primaries. These are Wyman, Sloan and Shirley's piecewise Gaussian
+/// fit.
+///
+/// https://jcgt.org/published/0002/02/01/
+pub fn cie_xyz(wavelength: f64) -> [f64; 3] {
+    let gaussian = |mean: f64, below: f64, above: f64| {
+        let deviation = if wavelength < mean { below } else { above };
+        let t = (wavelength - mean) / deviation;
+        (-0.5 * t * t).exp()
+    };
+
+    [
+        1.056 * gaussian(599.8, 37.9, 31.0) + 0.362 * gaussian(442.0, 16.0, 26.7)
+            - 0.065 * gaussian(501.1, 20.4, 26.2),
+        0.821 * gaussian(568.8, 46.9, 40.5) + 0.286 * gaussian(530.9, 16.3, 31.1),
+        1.217 * gaussian(437.0, 11.8, 36.0) + 0.681 * gaussian(459.0, 26.0, 13.8),
+    ]
+}
+
+/// Converts a color from CIE XYZ to linear sRGB.
+///
+/// https://en.wikipedia.org/wiki/SRGB#From_CIE_XYZ_to_sRGB
+pub fn xyz_to_rgb([x, y, z]: [f64; 3]) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// The color of a reflectance that varies with wavelength, found by
+/// sampling it evenly across the visible spectrum. A reflectance of 1 at
+/// every wavelength is white and colors outside of sRGB are clipped.
+pub fn to_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    let mut xyz = [0.0; 3];
+
+    for i in 0..SAMPLE_COUNT {
+        let value = reflectance(sample_wavelength(i));
+        for (j, matching) in cie_xyz(sample_wavelength(i)).iter().enumerate() {
+            xyz[j] += value * matching;
+        }
+    }
+
+    let color = xyz_to_rgb(xyz);
+    let white = white();
+    Color::new(
+        (color.r / white.r).max(0.0),
+        (color.g / white.g).max(0.0),
+        (color.b / white.b).max(0.0),
+    )
+}
+
+// The color of a reflectance of 1 at every wavelength that `to_rgb`
+// samples, before it's scaled to white.
+fn white() -> Color {
+    let mut xyz = [0.0; 3];
+    for i in 0..SAMPLE_COUNT {
+        for (j, matching) in cie_xyz(sample_wavelength(i)).iter().enumerate() {
+            xyz[j] += matching;
+        }
+    }
+    xyz_to_rgb(xyz)
+}
+
+fn sample_wavelength(i: usize) -> f64 {
+    let t = (i as f64 + 0.5) / SAMPLE_COUNT as f64;
+    MIN_WAVELENGTH + t * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+// Rises smoothly from 0 to 1 around the edge.
+fn smoothstep(wavelength: f64, edge: f64) -> f64 {
+    let t = ((wavelength - edge) / TRANSITION_WIDTH * 0.5 + 0.5).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::color::{BLACK, WHITE};
+    use super::*;
+
+    #[test]
+    fn upsampled_colors_average_back() {
+        let color = Color::new(0.7, 0.4, 0.1);
+        let (mut white_sum, mut color_sum) = (BLACK, BLACK);
+        let count = 300;
+        for i in 0..count {
+            let wavelengths = Wavelengths::sample((i as f64 + 0.5) / count as f64);
+            white_sum = white_sum.add(wavelengths.to_rgb(wavelengths.upsample(WHITE)));
+            color_sum = color_sum.add(wavelengths.to_rgb(wavelengths.upsample(color)));
+        }
+
+        let white = white_sum.scale(1.0 / count as f64);
+        let average = color_sum.scale(1.0 / count as f64);
+        for &channel in [white.r, white.g, white.b].iter() {
+            assert!((channel - 1.0).abs() < 0.02);
+        }
+        assert!(average.r > average.g && average.g > average.b);
+        assert!((average.luminance() - color.luminance()).abs() < 0.1);
+    }
+}
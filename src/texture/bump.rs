@@ -0,0 +1,73 @@
+use super::super::constants::EPSILON;
+use super::super::hit::{Hit, TextureCoordinate};
+use super::super::vector::Vector;
+use super::Texture;
+
+/// How far apart, in texture coordinates, heights are compared to find
+/// the slope of a bump map.
+const BUMP_DELTA: f64 = 0.0005;
+
+/// Tilts the hit's shading normal by a tangent-space normal map, whose
+/// red, green and blue channels store the x, y and z of the normal along
+/// the tangent, bitangent and shading normal. `strength` scales the tilt.
+///
+/// https://learnopengl.com/Advanced-Lighting/Normal-Mapping
+pub fn normal_map(texture: &dyn Texture, hit: &Hit, strength: f64) -> Vector {
+    let normal = hit.shading_normal;
+    let color = texture.color(hit);
+
+    // The tangent is made perpendicular to the normal, since smoothed
+    // normals drift away from the triangle's plane.
+    let tangent = hit
+        .tangent
+        .subtract(normal.scale(normal.dot_product(hit.tangent)))
+        .normalize();
+    let mut bitangent = normal.cross_product(tangent);
+    if bitangent.dot_product(hit.bitangent) < 0.0 {
+        bitangent = bitangent.scale(-1.0);
+    }
+
+    let perturbed = tangent
+        .scale((color.r * 2.0 - 1.0) * strength)
+        .add(bitangent.scale((color.g * 2.0 - 1.0) * strength))
+        .add(normal.scale(color.b * 2.0 - 1.0));
+
+    orient(perturbed, normal)
+}
+
+/// Tilts the hit's shading normal by the slope of a height map, as if the
+/// surface were displaced along its normal by `strength` times the
+/// height.
+///
+/// https://www.pbr-book.org/3ed-2018/Materials/Bump_Mapping
+pub fn bump_map(texture: &dyn Texture, hit: &Hit, strength: f64) -> Vector {
+    let normal = hit.shading_normal;
+    let height = texture.value(hit);
+
+    let mut shifted = *hit;
+    shifted.uv = TextureCoordinate::new(hit.uv.u + BUMP_DELTA, hit.uv.v);
+    let slope_u = (texture.value(&shifted) - height) / BUMP_DELTA;
+    shifted.uv = TextureCoordinate::new(hit.uv.u, hit.uv.v + BUMP_DELTA);
+    let slope_v = (texture.value(&shifted) - height) / BUMP_DELTA;
+
+    let tangent = hit.tangent.add(normal.scale(slope_u * strength));
+    let bitangent = hit.bitangent.add(normal.scale(slope_v * strength));
+
+    orient(tangent.cross_product(bitangent), normal)
+}
+
+/// Normalizes the perturbed normal and flips it onto the same side of the
+/// surface as the original, or falls back to the original if it is
+/// degenerate.
+fn orient(perturbed: Vector, normal: Vector) -> Vector {
+    if perturbed.length() < EPSILON {
+        return normal;
+    }
+
+    let perturbed = perturbed.normalize();
+    if perturbed.dot_product(normal) < 0.0 {
+        return perturbed.scale(-1.0);
+    }
+
+    perturbed
+}
@@ -0,0 +1,49 @@
+use super::super::color::Color;
+use super::super::hit::Hit;
+use super::{Mapping, Space, Texture};
+
+/// Alternates between two textures in squares of unit size, or cubes when
+/// `solid` is set.
+pub struct Checker {
+    pub mapping: Mapping,
+    pub even: Box<dyn Texture>,
+    pub odd: Box<dyn Texture>,
+    /// Whether the pattern also alternates along the Z-axis, which only
+    /// makes a difference in world space.
+    pub solid: bool,
+}
+
+impl Checker {
+    pub fn new(mapping: Mapping, even: Box<dyn Texture>, odd: Box<dyn Texture>) -> Checker {
+        Checker {
+            solid: mapping.space == Space::World,
+            mapping,
+            even,
+            odd,
+        }
+    }
+
+    fn texture(&self, hit: &Hit) -> &dyn Texture {
+        let point = self.mapping.point(hit);
+        let mut sum = point.x.floor() as i64 + point.y.floor() as i64;
+        if self.solid {
+            sum += point.z.floor() as i64;
+        }
+
+        if sum.rem_euclid(2) == 0 {
+            self.even.as_ref()
+        } else {
+            self.odd.as_ref()
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn color(&self, hit: &Hit) -> Color {
+        self.texture(hit).color(hit)
+    }
+
+    fn value(&self, hit: &Hit) -> f64 {
+        self.texture(hit).value(hit)
+    }
+}
@@ -0,0 +1,66 @@
+use super::super::color::{Color, BLACK};
+use super::super::hit::Hit;
+use super::super::vector::Vector;
+use super::{Mapping, Texture};
+
+/// How a gradient spreads out from the origin of its mapping.
+#[derive(Clone, Copy)]
+pub enum GradientShape {
+    /// Along the axis, by how far the point is along it.
+    Linear(Vector),
+    /// In every direction, by how far the point is from the origin.
+    Radial,
+}
+
+/// Blends between colors placed at positions from 0 to 1 along the shape.
+/// Points beyond either end take on the color there.
+pub struct Gradient {
+    pub mapping: Mapping,
+    pub shape: GradientShape,
+    /// Positions and their colors, in increasing order of position.
+    pub stops: Vec<(f64, Color)>,
+}
+
+impl Gradient {
+    pub fn new(mapping: Mapping, shape: GradientShape, stops: Vec<(f64, Color)>) -> Gradient {
+        Gradient {
+            mapping,
+            shape,
+            stops,
+        }
+    }
+
+    /// How far along the gradient the hit is.
+    fn position(&self, hit: &Hit) -> f64 {
+        let point = self.mapping.point(hit);
+        let position = match self.shape {
+            GradientShape::Linear(axis) => point.dot_product(axis),
+            GradientShape::Radial => point.length(),
+        };
+
+        position.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for Gradient {
+    fn color(&self, hit: &Hit) -> Color {
+        ramp(&self.stops, self.position(hit))
+    }
+}
+
+/// Blends between the colors of the stops on either side of the position,
+/// where the stops are in increasing order of position. Positions beyond
+/// either end take on the color there.
+pub(crate) fn ramp(stops: &[(f64, Color)], position: f64) -> Color {
+    let next = match stops.iter().position(|&(stop, _)| stop > position) {
+        Some(a) => a,
+        None => return stops.last().map_or(BLACK, |&(_, color)| color),
+    };
+    if next == 0 {
+        return stops[0].1;
+    }
+
+    let (start, start_color) = stops[next - 1];
+    let (end, end_color) = stops[next];
+    start_color.mix(end_color, (position - start) / (end - start))
+}
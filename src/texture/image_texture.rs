@@ -0,0 +1,146 @@
+use super::super::color::Color;
+use super::super::hit::{Hit, TextureCoordinate};
+use super::Texture;
+use std::path::Path;
+
+/// What happens to texture coordinates beyond the edges of the image.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    /// Tiles the image endlessly.
+    Repeat,
+    /// Stretches the pixels along the edges outwards.
+    Clamp,
+}
+
+/// Which of the image's channels `value` reads.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Channel {
+    Luminance,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+/// How the image's pixels are encoded. Color maps are usually saved in
+/// sRGB, while maps of other properties store their values as they are.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// Linear RGBA, from the top row of the image down.
+    pixels: Vec<[f64; 4]>,
+    pub wrap_mode: WrapMode,
+    pub channel: Channel,
+    /// Added to texture coordinates after they've been scaled.
+    pub offset: TextureCoordinate,
+    pub scale: TextureCoordinate,
+}
+
+impl ImageTexture {
+    pub fn open(path: &Path, color_space: ColorSpace) -> image::ImageResult<ImageTexture> {
+        let image = image::open(path)?.to_rgba8();
+        let (width, height) = image.dimensions();
+
+        let pixels = image
+            .pixels()
+            .map(|pixel| {
+                let mut values = [0.0; 4];
+                for (i, value) in values.iter_mut().enumerate() {
+                    *value = f64::from(pixel[i]) / 255.0;
+                    if i < 3 && color_space == ColorSpace::Srgb {
+                        *value = srgb_to_linear(*value);
+                    }
+                }
+                values
+            })
+            .collect();
+
+        Ok(ImageTexture {
+            width: width as usize,
+            height: height as usize,
+            pixels,
+            wrap_mode: WrapMode::Repeat,
+            channel: Channel::Luminance,
+            offset: TextureCoordinate::new(0.0, 0.0),
+            scale: TextureCoordinate::new(1.0, 1.0),
+        })
+    }
+
+    /// Blends the four pixels nearest to the coordinate by how close their
+    /// centers are to it.
+    ///
+    /// https://en.wikipedia.org/wiki/Bilinear_filtering
+    fn sample(&self, uv: TextureCoordinate) -> [f64; 4] {
+        let u = uv.u * self.scale.u + self.offset.u;
+        let v = uv.v * self.scale.v + self.offset.v;
+
+        // Images are stored from the top down, while v points up.
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let mut result = [0.0; 4];
+        for &(dx, dy, weight) in [
+            (0, 0, (1.0 - tx) * (1.0 - ty)),
+            (1, 0, tx * (1.0 - ty)),
+            (0, 1, (1.0 - tx) * ty),
+            (1, 1, tx * ty),
+        ]
+        .iter()
+        {
+            let column = self.wrap(x0 as i64 + dx, self.width);
+            let row = self.wrap(y0 as i64 + dy, self.height);
+            let pixel = self.pixels[row * self.width + column];
+            for (value, channel) in result.iter_mut().zip(pixel.iter()) {
+                *value += channel * weight;
+            }
+        }
+
+        result
+    }
+
+    fn wrap(&self, index: i64, length: usize) -> usize {
+        let length = length as i64;
+        match self.wrap_mode {
+            WrapMode::Repeat => index.rem_euclid(length) as usize,
+            WrapMode::Clamp => index.max(0).min(length - 1) as usize,
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn color(&self, hit: &Hit) -> Color {
+        let pixel = self.sample(hit.uv);
+        Color::new(pixel[0], pixel[1], pixel[2])
+    }
+
+    fn value(&self, hit: &Hit) -> f64 {
+        let pixel = self.sample(hit.uv);
+        match self.channel {
+            Channel::Luminance => Color::new(pixel[0], pixel[1], pixel[2]).luminance(),
+            Channel::Red => pixel[0],
+            Channel::Green => pixel[1],
+            Channel::Blue => pixel[2],
+            Channel::Alpha => pixel[3],
+        }
+    }
+}
+
+/// https://en.wikipedia.org/wiki/SRGB#From_sRGB_to_CIE_XYZ
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        return value / 12.92;
+    }
+
+    ((value + 0.055) / 1.055).powf(2.4)
+}
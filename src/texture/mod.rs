@@ -0,0 +1,95 @@
+pub mod bump;
+mod checker;
+mod gradient;
+mod image_texture;
+mod noise;
+mod voronoi;
+
+pub use checker::Checker;
+pub(crate) use gradient::ramp;
+pub use gradient::{Gradient, GradientShape};
+pub use image_texture::{Channel, ColorSpace, ImageTexture, WrapMode};
+pub use noise::{Noise, NoiseKind};
+pub use voronoi::{Voronoi, VoronoiOutput};
+
+use super::color::Color;
+use super::hit::Hit;
+use super::vector::Vector;
+use std::path::{Path, PathBuf};
+
+/// Anything that varies a material's properties across a surface.
+pub trait Texture: Sync {
+    fn color(&self, hit: &Hit) -> Color;
+
+    /// A single value for properties that aren't colors, such as opacity.
+    fn value(&self, hit: &Hit) -> f64 {
+        self.color(hit).luminance()
+    }
+}
+
+/// The same color everywhere, which is mostly useful as an input to other
+/// textures.
+pub struct ConstantTexture {
+    pub color: Color,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Color) -> ConstantTexture {
+        ConstantTexture { color }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn color(&self, _hit: &Hit) -> Color {
+        self.color
+    }
+}
+
+/// Where procedural textures are evaluated.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Space {
+    /// The hit's texture coordinates, as (u, v, 0), so that patterns
+    /// follow the surface like images do.
+    Uv,
+    /// The hit's position, so that patterns are carved out of solid space
+    /// and don't stretch across seams.
+    World,
+}
+
+/// Places a procedural texture's pattern, which is evaluated at the point
+/// in the space multiplied by `scale` and then moved by `offset`.
+#[derive(Clone, Copy)]
+pub struct Mapping {
+    pub space: Space,
+    pub scale: Vector,
+    pub offset: Vector,
+}
+
+impl Mapping {
+    pub fn new(space: Space) -> Mapping {
+        Mapping {
+            space,
+            scale: Vector::new(1.0, 1.0, 1.0),
+            offset: Vector::zeros(),
+        }
+    }
+
+    pub fn point(&self, hit: &Hit) -> Vector {
+        let point = match self.space {
+            Space::Uv => Vector::new(hit.uv.u, hit.uv.v, 0.0),
+            Space::World => hit.position,
+        };
+
+        point.multiply(self.scale).add(self.offset)
+    }
+}
+
+/// The path within the first search path it's found in, or as it is if
+/// none of them have it.
+pub(crate) fn find_file(path: &Path, search_paths: &[PathBuf]) -> PathBuf {
+    search_paths
+        .iter()
+        .map(|directory| directory.join(path))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| path.to_path_buf())
+}
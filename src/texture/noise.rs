@@ -0,0 +1,188 @@
+use super::super::color::{self, Color};
+use super::super::hit::Hit;
+use super::super::random;
+use super::super::vector::Vector;
+use super::{Mapping, Texture};
+
+/// How layers of Perlin noise are combined.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NoiseKind {
+    /// A single layer, which makes soft blobs.
+    Perlin,
+    /// Fractional Brownian motion, which adds ever finer and fainter
+    /// layers for detail like that of clouds.
+    Fbm,
+    /// Like fBm, but with the absolute value of each layer, which creases
+    /// it like marble veins or fire.
+    Turbulence,
+}
+
+/// Blends between two textures by a noise value from 0 to 1.
+///
+/// https://mrl.cs.nyu.edu/~perlin/paper445.pdf
+pub struct Noise {
+    pub mapping: Mapping,
+    pub kind: NoiseKind,
+    /// How many layers fBm and turbulence add together.
+    pub octaves: usize,
+    /// How much finer each layer is than the last.
+    pub lacunarity: f64,
+    /// How much fainter each layer is than the last.
+    pub gain: f64,
+    /// Picks a different pattern of noise.
+    pub seed: u64,
+    pub low: Box<dyn Texture>,
+    pub high: Box<dyn Texture>,
+}
+
+impl Noise {
+    pub fn new(
+        mapping: Mapping,
+        kind: NoiseKind,
+        low: Box<dyn Texture>,
+        high: Box<dyn Texture>,
+    ) -> Noise {
+        Noise {
+            mapping,
+            kind,
+            octaves: 6,
+            lacunarity: 2.0,
+            gain: 0.5,
+            seed: 0,
+            low,
+            high,
+        }
+    }
+
+    fn noise(&self, hit: &Hit) -> f64 {
+        let point = self.mapping.point(hit);
+        if self.kind == NoiseKind::Perlin {
+            return 0.5 + 0.5 * perlin(self.seed, point);
+        }
+
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut frequency = 1.0;
+        for octave in 0..self.octaves.max(1) {
+            // Each layer is shifted off of the others' lattices, whose
+            // points would otherwise line up as a visible grid of zeros.
+            let shift = Vector::new(0.31, 0.57, 0.83).scale(octave as f64);
+            let layer = perlin(self.seed, point.scale(frequency).add(shift));
+            sum += amplitude
+                * match self.kind {
+                    NoiseKind::Turbulence => layer.abs(),
+                    _ => layer,
+                };
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        sum /= total_amplitude;
+
+        match self.kind {
+            NoiseKind::Turbulence => sum.clamp(0.0, 1.0),
+            _ => (0.5 + 0.5 * sum).clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Texture for Noise {
+    fn color(&self, hit: &Hit) -> Color {
+        self.low
+            .color(hit)
+            .mix(self.high.color(hit), self.noise(hit))
+    }
+
+    fn value(&self, hit: &Hit) -> f64 {
+        color::mix(self.low.value(hit), self.high.value(hit), self.noise(hit))
+    }
+}
+
+/// Improved Perlin noise, which smoothly varies between about -1 and 1
+/// and is 0 at every point on the integer lattice. The gradient at each
+/// lattice point is picked by hashing it rather than by a table, so that
+/// the seed can pick a different pattern.
+///
+/// https://mrl.cs.nyu.edu/~perlin/noise/
+pub fn perlin(seed: u64, point: Vector) -> f64 {
+    let cell = [point.x.floor(), point.y.floor(), point.z.floor()];
+    let x = point.x - cell[0];
+    let y = point.y - cell[1];
+    let z = point.z - cell[2];
+
+    let gradient = |dx: i64, dy: i64, dz: i64| {
+        let hash = [
+            cell[0] as i64 + dx,
+            cell[1] as i64 + dy,
+            cell[2] as i64 + dz,
+        ]
+        .iter()
+        .fold(seed, |hash, &coordinate| {
+            random::hash(hash, coordinate as u64)
+        });
+        grad(hash, x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+
+    let u = fade(x);
+    let v = fade(y);
+    let w = fade(z);
+
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, gradient(0, 0, 0), gradient(1, 0, 0)),
+            lerp(u, gradient(0, 1, 0), gradient(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, gradient(0, 0, 1), gradient(1, 0, 1)),
+            lerp(u, gradient(0, 1, 1), gradient(1, 1, 1)),
+        ),
+    )
+}
+
+/// Eases from 0 to 1 with no change in slope or curvature at either end.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// The dot product of the offset with one of the twelve directions from
+/// the center of a cube to its edges, as picked by the hash.
+fn grad(hash: u64, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_is_smooth_and_bounded() {
+        let mut previous = perlin(7, Vector::new(0.0, 0.3, 0.6));
+        for i in 1..10_000 {
+            let t = i as f64 * 0.001;
+            let value = perlin(7, Vector::new(t, 0.3 + t * 0.5, 0.6 - t));
+            assert!(value.abs() <= 1.0);
+            assert!((value - previous).abs() < 0.01);
+            previous = value;
+        }
+
+        assert_eq!(perlin(7, Vector::new(3.0, -2.0, 5.0)), 0.0);
+    }
+}
@@ -0,0 +1,100 @@
+use super::super::color::Color;
+use super::super::hit::Hit;
+use super::super::random;
+use super::super::vector::Vector;
+use super::{Mapping, Texture};
+
+/// What a Voronoi texture shows of the cells around each point.
+#[derive(Clone, Copy, PartialEq)]
+pub enum VoronoiOutput {
+    /// The distance to the nearest cell's center, which is dark at the
+    /// centers and bright where cells meet.
+    Distance,
+    /// How much closer the nearest cell's center is than the next one's,
+    /// which is dark along the borders between cells, like cracks.
+    Edge,
+    /// A random color for each cell, like stained glass.
+    Cell,
+}
+
+/// Cellular noise, which scatters one point in every unit cube of space
+/// and divides space up by which point is nearest.
+///
+/// https://www.rhythmiccanvas.com/research/papers/worley.pdf
+pub struct Voronoi {
+    pub mapping: Mapping,
+    pub output: VoronoiOutput,
+    /// Picks a different scattering of points.
+    pub seed: u64,
+}
+
+impl Voronoi {
+    pub fn new(mapping: Mapping, output: VoronoiOutput) -> Voronoi {
+        Voronoi {
+            mapping,
+            output,
+            seed: 0,
+        }
+    }
+
+    /// The distances to the nearest and second nearest points, along with
+    /// the hash of the nearest one's cube.
+    fn nearest(&self, hit: &Hit) -> (f64, f64, u64) {
+        let point = self.mapping.point(hit);
+        let cube = [
+            point.x.floor() as i64,
+            point.y.floor() as i64,
+            point.z.floor() as i64,
+        ];
+
+        let mut nearest = (f64::MAX, f64::MAX, 0);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbor = [cube[0] + dx, cube[1] + dy, cube[2] + dz];
+                    let hash = neighbor.iter().fold(self.seed, |hash, &coordinate| {
+                        random::hash(hash, coordinate as u64)
+                    });
+
+                    let center = Vector::new(
+                        neighbor[0] as f64 + unit(random::hash(hash, 0)),
+                        neighbor[1] as f64 + unit(random::hash(hash, 1)),
+                        neighbor[2] as f64 + unit(random::hash(hash, 2)),
+                    );
+                    let distance = center.subtract(point).length();
+
+                    if distance < nearest.0 {
+                        nearest = (distance, nearest.0, hash);
+                    } else if distance < nearest.1 {
+                        nearest.1 = distance;
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+impl Texture for Voronoi {
+    fn color(&self, hit: &Hit) -> Color {
+        let (nearest, second, hash) = self.nearest(hit);
+        match self.output {
+            VoronoiOutput::Distance => Color::new(nearest, nearest, nearest),
+            VoronoiOutput::Edge => {
+                let edge = second - nearest;
+                Color::new(edge, edge, edge)
+            }
+            VoronoiOutput::Cell => Color::new(
+                unit(random::hash(hash, 3)),
+                unit(random::hash(hash, 4)),
+                unit(random::hash(hash, 5)),
+            ),
+        }
+    }
+}
+
+/// Maps a hash onto [0, 1).
+fn unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}